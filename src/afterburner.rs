@@ -0,0 +1,152 @@
+//! MSI Afterburner stores its curve in profile files as a `VFCurve=` hex blob:
+//! a little-endian u32 format version and u32 point count, followed by one
+//! `f32` triplet per point of voltage (mV), base frequency (MHz) and offset (MHz).
+
+use nvapi::{Kilohertz, KilohertzDelta, Microvolts, VfPoint};
+use crate::Error;
+
+pub const PROFILE_KEY: &'static str = "VFCurve";
+const VERSION: u32 = 0x00020000;
+const HEADER_LEN: usize = 8;
+const POINT_LEN: usize = 12;
+
+fn hex_decode(hex: &str) -> Result<Vec<u8>, Error> {
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("invalid Afterburner curve data".into())
+    }
+
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(From::from))
+        .collect()
+}
+
+fn f32_at(data: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Finds the curve blob in either an Afterburner profile or a bare hex string.
+fn find_blob(data: &str) -> &str {
+    data.lines()
+        .map(str::trim)
+        .filter_map(|line| {
+            let mut kv = line.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) if key.trim() == PROFILE_KEY => Some(value.trim()),
+                _ => None,
+            }
+        }).next()
+        .unwrap_or_else(|| data.trim())
+}
+
+/// Whether `data` looks like something `decode` can read.
+pub fn detect(data: &str) -> bool {
+    let blob = find_blob(data);
+    blob.len() >= HEADER_LEN * 2 && blob.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+pub fn decode(data: &str) -> Result<Vec<VfPoint>, Error> {
+    let data = hex_decode(find_blob(data))?;
+    if data.len() < HEADER_LEN {
+        return Err("invalid Afterburner curve header".into())
+    }
+
+    let version = u32::from_le_bytes(data[0..4].try_into().unwrap());
+    if version != VERSION {
+        return Err("unsupported Afterburner curve version".into())
+    }
+
+    let count = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    if data.len() < HEADER_LEN + count * POINT_LEN {
+        return Err("truncated Afterburner curve".into())
+    }
+
+    Ok((0..count).map(|i| HEADER_LEN + i * POINT_LEN)
+        .map(|offset| (f32_at(&data, offset), f32_at(&data, offset + 4), f32_at(&data, offset + 8)))
+        // unused slots at the end of the table are zeroed
+        .filter(|&(voltage, _, _)| voltage > 0.0)
+        .map(|(voltage, frequency, offset)| {
+            let delta = KilohertzDelta((offset * 1000.0).round() as i32);
+            VfPoint {
+                voltage: Microvolts((voltage * 1000.0).round() as u32),
+                frequency: Kilohertz((frequency * 1000.0).round() as u32) + delta,
+                delta: delta,
+            }
+        }).collect())
+}
+
+pub fn encode<'a, I: IntoIterator<Item=&'a VfPoint>>(points: I) -> String {
+    let points = points.into_iter().collect::<Vec<_>>();
+
+    let mut data = Vec::with_capacity(HEADER_LEN + points.len() * POINT_LEN);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&(points.len() as u32).to_le_bytes());
+    for point in points {
+        let base = point.frequency - point.delta;
+        data.extend_from_slice(&(point.voltage.0 as f32 / 1000.0).to_le_bytes());
+        data.extend_from_slice(&(base.0 as f32 / 1000.0).to_le_bytes());
+        data.extend_from_slice(&(point.delta.0 as f32 / 1000.0).to_le_bytes());
+    }
+
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nvapi::{Kilohertz, KilohertzDelta, Microvolts, VfPoint};
+    use super::{decode, encode, PROFILE_KEY};
+
+    /// Two points followed by a zeroed slot, as saved by Afterburner.
+    const PROFILE: &'static str = "[Startup]\r\n\
+        Format=2\r\n\
+        VFCurve=00000200030000000000484400808B44000048420080544400004844000048C1000000000000000000000000\r\n";
+
+    fn point(voltage: u32, frequency: u32, delta: i32) -> VfPoint {
+        VfPoint {
+            voltage: Microvolts(voltage),
+            frequency: Kilohertz(frequency),
+            delta: KilohertzDelta(delta),
+        }
+    }
+
+    fn assert_points(points: &[VfPoint], expected: &[VfPoint]) {
+        assert_eq!(points.len(), expected.len());
+        for (point, expected) in points.iter().zip(expected) {
+            assert_eq!(point.voltage, expected.voltage);
+            assert_eq!(point.frequency, expected.frequency);
+            assert_eq!(point.delta, expected.delta);
+        }
+    }
+
+    #[test]
+    fn decode_profile() {
+        assert_points(&decode(PROFILE).unwrap(), &[
+            point(800000, 1166000, 50000),
+            point(850000, 787500, -12500),
+        ]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let points = [
+            point(700000, 1200000, 0),
+            point(812500, 1530000, 45000),
+            point(1050000, 1900000, -15000),
+        ];
+        let blob = encode(&points);
+        assert_points(&decode(&blob).unwrap(), &points);
+        assert_points(&decode(&format!("{}={}", PROFILE_KEY, blob)).unwrap(), &points);
+    }
+
+    #[test]
+    fn truncated() {
+        // claims three points but only carries two
+        let blob = "000002000300000000004844";
+        assert_eq!(decode(blob).unwrap_err().to_string(), "truncated Afterburner curve");
+    }
+
+    #[test]
+    fn wrong_version() {
+        let blob = "0000010000000000";
+        assert_eq!(decode(blob).unwrap_err().to_string(), "unsupported Afterburner curve version");
+    }
+}
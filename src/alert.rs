@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::process::{Child, Command};
+use std::str::FromStr;
+use std::time::Duration;
+use log::{info, warn};
+use serde::Deserialize;
+use nvapi::GpuStatus;
+use crate::sample::{self, key, Metric};
+use crate::Error;
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn parse(op: &str) -> Result<Self, Error> {
+        Ok(match op {
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            "==" | "=" => Op::Eq,
+            "!=" => Op::Ne,
+            _ => return Err("unknown alert comparison".into()),
+        })
+    }
+
+    fn compare(&self, a: f64, b: f64) -> bool {
+        match *self {
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    /// Holds when any metric matching the name compares true, such as `temperature > 83C`.
+    Metric { metric: String, op: Op, value: f64 },
+    /// Share of samples in the window a perf limit was active, such as `limit power > 50%`.
+    Limit { limit: String, op: Op, share: f64 },
+}
+
+/// Strips a trailing unit such as `C`, `%` or `MHz`, metrics are compared in their own units.
+fn parse_value(value: &str) -> Result<f64, Error> {
+    let value = value.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%');
+    Ok(f64::from_str(value)?)
+}
+
+fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let (value, scale) = if let Some(value) = value.strip_suffix("ms") {
+        (value, 0.001)
+    } else if let Some(value) = value.strip_suffix('s') {
+        (value, 1.0)
+    } else if let Some(value) = value.strip_suffix('m') {
+        (value, 60.0)
+    } else {
+        (value, 1.0)
+    };
+
+    Ok(Duration::from_secs_f64(f64::from_str(value)? * scale))
+}
+
+impl Condition {
+    fn parse(tokens: &[&str]) -> Result<Self, Error> {
+        match *tokens {
+            ["limit", limit, op, share] | ["limit", limit, op, share, "of", "samples"] => Ok(Condition::Limit {
+                limit: key(limit),
+                op: Op::parse(op)?,
+                share: parse_value(share)? / 100.0,
+            }),
+            [metric, op, value] => Ok(Condition::Metric {
+                metric: metric.into(),
+                op: Op::parse(op)?,
+                value: parse_value(value)?,
+            }),
+            _ => Err("alert conditions look like `METRIC > VALUE` or `limit NAME > SHARE%`".into()),
+        }
+    }
+
+    fn matches(metric: &Metric, name: &str) -> bool {
+        metric.name == name || metric.base_name() == name || metric.column() == name
+    }
+
+    /// Whether this condition held for a single sample.
+    fn sample(&self, metrics: &[Metric], limits: &[String]) -> bool {
+        match *self {
+            Condition::Metric { ref metric, op, value } =>
+                metrics.iter().any(|m| Self::matches(m, metric) && op.compare(m.value, value)),
+            Condition::Limit { ref limit, .. } =>
+                limits.iter().any(|l| key(l) == *limit),
+        }
+    }
+
+    fn describe(&self, metrics: &[Metric]) -> Vec<String> {
+        match *self {
+            Condition::Metric { ref metric, .. } => metrics.iter()
+                .filter(|m| Self::matches(m, metric))
+                .map(|m| format!("{}={}", m.column(), m.value))
+                .collect(),
+            Condition::Limit { ref limit, .. } => vec![format!("limit={}", limit)],
+        }
+    }
+}
+
+/// A parsed rule such as `tachometer == 0 while cooler > 30% for 10s`.
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    condition: String,
+    command: Option<String>,
+    conditions: Vec<Condition>,
+    window: Option<Duration>,
+}
+
+impl Rule {
+    fn parse(config: RuleConfig, command: Option<&str>) -> Result<Self, Error> {
+        let tokens = config.condition.split_whitespace().collect::<Vec<_>>();
+        let (tokens, window) = match tokens.iter().position(|&t| t == "for") {
+            Some(i) if i + 2 == tokens.len() => (&tokens[..i], Some(parse_duration(tokens[i + 1])?)),
+            Some(..) => return Err("`for DURATION` must end an alert condition".into()),
+            None => (&tokens[..], None),
+        };
+
+        Ok(Rule {
+            conditions: tokens.split(|&t| t == "while").map(Condition::parse).collect::<Result<_, _>>()?,
+            window: window,
+            command: config.command.or_else(|| command.map(From::from)),
+            condition: config.condition,
+            name: config.name,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RuleConfig {
+    name: String,
+    condition: String,
+    command: Option<String>,
+}
+
+/// Alert rules file, `command` is the default hook for rules that don't set their own.
+#[derive(Debug, Clone, Deserialize)]
+struct Config {
+    command: Option<String>,
+    #[serde(default)]
+    rule: Vec<RuleConfig>,
+}
+
+/// Per-sample condition results for one rule on one GPU.
+#[derive(Debug, Default)]
+struct RuleState {
+    samples: VecDeque<(Duration, Vec<bool>)>,
+    active: bool,
+}
+
+pub struct Alerts {
+    rules: Vec<Rule>,
+    states: Vec<Vec<RuleState>>,
+    children: Vec<Child>,
+}
+
+impl Alerts {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        let command = config.command;
+
+        Ok(Alerts {
+            rules: config.rule.into_iter().map(|rule| Rule::parse(rule, command.as_deref())).collect::<Result<_, _>>()?,
+            states: Vec::new(),
+            children: Vec::new(),
+        })
+    }
+
+    /// Feeds a GPU's sample through every rule, running hooks for rules that fire or clear.
+    pub fn evaluate(&mut self, elapsed: Duration, gpu: usize, status: &GpuStatus) {
+        let metrics = sample::metrics(status);
+        let limits = sample::limits(status);
+
+        while self.states.len() <= gpu {
+            self.states.push(self.rules.iter().map(|_| Default::default()).collect());
+        }
+
+        for (rule, state) in self.rules.iter().zip(&mut self.states[gpu]) {
+            let results = rule.conditions.iter().map(|c| c.sample(&metrics, &limits)).collect();
+            state.samples.push_back((elapsed, results));
+
+            // keep the latest sample at or before the start of the window, so a
+            // full window can be told apart from monitoring having just started
+            let start = match rule.window {
+                Some(window) => elapsed.checked_sub(window),
+                None => Some(elapsed),
+            };
+            if let Some(start) = start {
+                while state.samples.len() > 1 && state.samples[1].0 <= start {
+                    state.samples.pop_front();
+                }
+            }
+            let full = start.map(|start| state.samples[0].0 <= start).unwrap_or(false);
+
+            let active = full && rule.conditions.iter().enumerate().all(|(i, condition)| {
+                let held = state.samples.iter().filter(|&&(_, ref results)| results[i]).count();
+                match *condition {
+                    Condition::Metric { .. } => held == state.samples.len(),
+                    Condition::Limit { op, share, .. } => op.compare(held as f64 / state.samples.len() as f64, share),
+                }
+            });
+
+            if active != state.active {
+                state.active = active;
+
+                let values = rule.conditions.iter().flat_map(|c| c.describe(&metrics)).collect::<Vec<_>>().join(" ");
+                let event = if active { "fired" } else { "cleared" };
+                info!("GPU {}: alert {} {} ({})", gpu, rule.name, event, values);
+
+                if let Some(ref command) = rule.command {
+                    match Self::spawn(command, rule, event, gpu, &values) {
+                        Ok(child) => self.children.push(child),
+                        Err(e) => warn!("Failed to run alert hook for {}: {}", rule.name, e),
+                    }
+                }
+            }
+        }
+
+        // reap hooks that have finished so they don't linger
+        self.children.retain_mut(|child| !matches!(child.try_wait(), Ok(Some(..))));
+    }
+
+    fn spawn(command: &str, rule: &Rule, event: &str, gpu: usize, values: &str) -> Result<Child, Error> {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+
+        Ok(shell.arg(command)
+            .env("NVOCLOCK_ALERT", &rule.name)
+            .env("NVOCLOCK_ALERT_EVENT", event)
+            .env("NVOCLOCK_ALERT_CONDITION", &rule.condition)
+            .env("NVOCLOCK_GPU", gpu.to_string())
+            .env("NVOCLOCK_VALUES", values)
+            .spawn()?)
+    }
+}
@@ -0,0 +1,112 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use crossterm::terminal;
+use nvapi::{Microvolts, VfPoint};
+
+const HEIGHT: usize = 20;
+const DEFAULT_WIDTH: usize = 80;
+const MIN_WIDTH: usize = 20;
+const MIN_HEIGHT: usize = 4;
+const AXIS_LEN: usize = 9;
+
+const BASE: char = '·';
+const OFFSET: char = '•';
+const CORE: char = '*';
+const LOCK: char = '^';
+
+const SPARKS: &'static [char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The width of the terminal stdout is attached to, or `$COLUMNS` when output
+/// is piped, since the controlling terminal's size wouldn't apply then.
+pub fn terminal_width() -> usize {
+    let columns = if io::stdout().is_terminal() {
+        terminal::size().ok().map(|(columns, _)| columns as usize).filter(|&columns| columns > 0)
+    } else {
+        None
+    };
+
+    columns
+        .or_else(|| env::var("COLUMNS").ok().and_then(|c| c.parse().ok()))
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+fn scale(value: f64, min: f64, max: f64, len: usize) -> usize {
+    if max <= min || len < 2 {
+        return 0
+    }
+
+    (((value - min) / (max - min)) * (len - 1) as f64).round() as usize
+}
+
+fn mhz(khz: f64) -> String {
+    format!("{:.0} MHz", khz / 1000.0)
+}
+
+/// Plots frequency against voltage, overlaying the base curve and the curve with offsets applied.
+pub fn render_vfp<I: Iterator<Item=(usize, VfPoint)>>(vfp: I, lock: Option<Microvolts>, core: Option<Microvolts>, width: usize, height: usize) -> Vec<String> {
+    let points = vfp.map(|(_, point)| point).collect::<Vec<_>>();
+    if points.is_empty() {
+        return Vec::new()
+    }
+
+    let width = width.saturating_sub(AXIS_LEN + 2).max(MIN_WIDTH);
+    let height = height.max(MIN_HEIGHT);
+
+    let voltages = points.iter().map(|p| p.voltage.0 as f64);
+    let (vmin, vmax) = voltages.fold((f64::MAX, f64::MIN), |(min, max), v| (min.min(v), max.max(v)));
+    let frequencies = points.iter()
+        .flat_map(|p| vec![(p.frequency - p.delta).0 as f64, p.frequency.0 as f64]);
+    let (fmin, fmax) = frequencies.fold((f64::MAX, f64::MIN), |(min, max), f| (min.min(f), max.max(f)));
+
+    let mut grid = vec![vec![' '; width]; height];
+    for point in &points {
+        let x = scale(point.voltage.0 as f64, vmin, vmax, width);
+        let base = height - 1 - scale((point.frequency - point.delta).0 as f64, fmin, fmax, height);
+        let offset = height - 1 - scale(point.frequency.0 as f64, fmin, fmax, height);
+
+        if grid[base][x] == ' ' {
+            grid[base][x] = BASE;
+        }
+        grid[offset][x] = if Some(point.voltage) == lock {
+            LOCK
+        } else if Some(point.voltage) == core {
+            CORE
+        } else {
+            OFFSET
+        };
+    }
+
+    let mut lines = grid.into_iter().enumerate().map(|(row, line)| {
+        let label = match row {
+            0 => mhz(fmax),
+            row if row == height / 2 => mhz((fmin + fmax) / 2.0),
+            row if row == height - 1 => mhz(fmin),
+            _ => String::new(),
+        };
+        format!("{:>width$} │{}", label, line.into_iter().collect::<String>(), width = AXIS_LEN)
+    }).collect::<Vec<_>>();
+
+    let left = format!("{:.0} mV", vmin / 1000.0);
+    let right = format!("{:.0} mV", vmax / 1000.0);
+    lines.push(format!("{:>width$} └{}", "", "─".repeat(width), width = AXIS_LEN));
+    lines.push(format!("{:>width$}  {}{:>pad$}", "", left, right,
+        width = AXIS_LEN, pad = width.saturating_sub(left.len())));
+    lines.push(format!("{:>width$}  {} Base  {} Offset  {} Core Voltage  {} Lock", "",
+        BASE, OFFSET, CORE, LOCK, width = AXIS_LEN));
+
+    lines
+}
+
+pub fn print_vfp<I: Iterator<Item=(usize, VfPoint)>>(vfp: I, lock: Option<Microvolts>, core: Option<Microvolts>, width: usize) {
+    for line in render_vfp(vfp, lock, core, width, HEIGHT) {
+        println!("{}", line);
+    }
+}
+
+/// The most recent `width` values as a row of block characters, scaled to their own range.
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    let values = &values[values.len().saturating_sub(width)..];
+    let (min, max) = values.iter().fold((f64::MAX, f64::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+
+    values.iter().map(|&v| SPARKS[scale(v, min, max, SPARKS.len())]).collect()
+}
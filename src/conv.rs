@@ -1,5 +1,5 @@
-use nvapi::{PState, CoolerPolicy, ClockDomain};
-use crate::types::{ResetSettings, OutputFormat};
+use nvapi::{PState, CoolerPolicy, ClockDomain, ClockLockMode};
+use crate::types::{ResetSettings, OutputFormat, MetricGroup, CurveFormat, CurveFix};
 use crate::Error;
 
 pub trait ConvertEnum: Sized {
@@ -57,6 +57,10 @@ enum_from_str! {
     OutputFormat => {
         Human = "human",
         Json = "json",
+        Ndjson = "ndjson",
+        Yaml = "yaml",
+        Toml = "toml",
+        Influx = "influx",
         _ => "unknown output format",
     }
 }
@@ -75,6 +79,41 @@ enum_from_str! {
     }
 }
 
+enum_from_str! {
+    MetricGroup => {
+        Clocks = "clocks",
+        Utilization = "utilization",
+        Sensors = "sensors",
+        Power = "power",
+        Voltage = "voltage",
+        Tachometer = "tachometer",
+        Coolers = "coolers",
+        PState = "pstate",
+        Limits = "limits",
+        _ => "unknown metric",
+    }
+}
+
+enum_from_str! {
+    CurveFormat => {
+        Csv = "csv",
+        Json = "json",
+        Toml = "toml",
+        Afterburner = "afterburner",
+        Svg = "svg",
+        _ => "unknown curve format",
+    }
+}
+
+enum_from_str! {
+    CurveFix => {
+        Clamp = "clamp",
+        Smooth = "smooth",
+        Quantize = "quantize",
+        _ => "unknown curve fix",
+    }
+}
+
 enum_from_str! {
     PState => {
         P0 = "P0",
@@ -107,6 +146,14 @@ enum_from_str! {
     }
 }
 
+enum_from_str! {
+    ClockLockMode => {
+        None = "none",
+        Manual = "manual",
+        _ => "unknown lock mode",
+    }
+}
+
 enum_from_str! {
     CoolerPolicy => {
         None = "default",
@@ -0,0 +1,345 @@
+use std::fmt;
+use serde::Serialize;
+use nvapi::{Kilohertz, KilohertzDelta, Microvolts, Range, VfPoint};
+use crate::types::CurveFix;
+
+/// Constraints a curve is checked against before it is handed to the driver.
+#[derive(Debug, Clone)]
+pub struct CurveLimits {
+    pub range: Option<Range<KilohertzDelta>>,
+    /// The driver's frequency bin size, offsets aren't checked against a grid without one.
+    pub step: Option<KilohertzDelta>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CurveIssue {
+    NonMonotonic {
+        index: usize,
+        voltage: Microvolts,
+        frequency: Kilohertz,
+        previous: Kilohertz,
+    },
+    OutOfRange {
+        index: usize,
+        voltage: Microvolts,
+        delta: KilohertzDelta,
+        range: Range<KilohertzDelta>,
+    },
+    OffGrid {
+        index: usize,
+        voltage: Microvolts,
+        delta: KilohertzDelta,
+        step: KilohertzDelta,
+    },
+}
+
+impl fmt::Display for CurveIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CurveIssue::NonMonotonic { index, voltage, frequency, previous } =>
+                write!(f, "point {} @ {}: frequency {} is lower than the previous point's {}", index, voltage, frequency, previous),
+            CurveIssue::OutOfRange { index, voltage, delta, ref range } =>
+                write!(f, "point {} @ {}: offset {} is outside of the allowed {} range", index, voltage, delta, range),
+            CurveIssue::OffGrid { index, voltage, delta, step } =>
+                write!(f, "warning: point {} @ {}: offset {} is not a multiple of {} and will be rounded by the driver", index, voltage, delta, step),
+        }
+    }
+}
+
+impl CurveIssue {
+    /// Issues the driver deals with on its own, which shouldn't stop a curve from being applied.
+    pub fn is_warning(&self) -> bool {
+        match *self {
+            CurveIssue::OffGrid { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Point indices ordered by voltage, the order the driver walks the curve in.
+fn voltage_order(points: &[VfPoint]) -> Vec<usize> {
+    let mut order = (0..points.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| points[i].voltage);
+    order
+}
+
+fn on_grid(delta: KilohertzDelta, step: KilohertzDelta) -> bool {
+    step.0 <= 0 || delta.0 % step.0 == 0
+}
+
+fn quantize(delta: KilohertzDelta, step: KilohertzDelta) -> KilohertzDelta {
+    if step.0 <= 0 {
+        return delta
+    }
+
+    KilohertzDelta((delta.0 as f64 / step.0 as f64).round() as i32 * step.0)
+}
+
+fn set_delta(point: &mut VfPoint, delta: KilohertzDelta) {
+    let base = point.frequency - point.delta;
+    point.delta = delta;
+    point.frequency = base + delta;
+}
+
+pub fn validate(points: &[VfPoint], limits: &CurveLimits) -> Vec<CurveIssue> {
+    let mut issues = Vec::new();
+
+    for (index, point) in points.iter().enumerate() {
+        if let Some(ref range) = limits.range {
+            if point.delta < range.min || point.delta > range.max {
+                issues.push(CurveIssue::OutOfRange {
+                    index: index,
+                    voltage: point.voltage,
+                    delta: point.delta,
+                    range: range.clone(),
+                });
+            }
+        }
+
+        if let Some(step) = limits.step.filter(|&step| !on_grid(point.delta, step)) {
+            issues.push(CurveIssue::OffGrid {
+                index: index,
+                voltage: point.voltage,
+                delta: point.delta,
+                step: step,
+            });
+        }
+    }
+
+    let mut previous: Option<Kilohertz> = None;
+    for index in voltage_order(points) {
+        let point = &points[index];
+        match previous {
+            Some(previous) if point.frequency < previous => issues.push(CurveIssue::NonMonotonic {
+                index: index,
+                voltage: point.voltage,
+                frequency: point.frequency,
+                previous: previous,
+            }),
+            _ => previous = Some(point.frequency),
+        }
+    }
+
+    issues
+}
+
+pub fn fix(points: &mut [VfPoint], fix: CurveFix, limits: &CurveLimits) {
+    match fix {
+        CurveFix::Clamp => if let Some(ref range) = limits.range {
+            for point in points.iter_mut() {
+                let delta = point.delta.max(range.min).min(range.max);
+                set_delta(point, delta);
+            }
+        },
+        CurveFix::Quantize => if let Some(step) = limits.step {
+            for point in points.iter_mut() {
+                let mut delta = quantize(point.delta, step);
+                if let Some(ref range) = limits.range {
+                    // rounding must not push a valid offset out of range
+                    if delta > range.max {
+                        delta = delta - step;
+                    } else if delta < range.min {
+                        delta = delta + step;
+                    }
+                }
+                set_delta(point, delta);
+            }
+        },
+        CurveFix::Smooth => {
+            let mut previous: Option<Kilohertz> = None;
+            for index in voltage_order(points) {
+                let point = &mut points[index];
+                if let Some(previous) = previous {
+                    if point.frequency < previous {
+                        let delta = point.delta + (previous - point.frequency);
+                        set_delta(point, delta);
+                    }
+                }
+                previous = Some(point.frequency);
+            }
+        },
+    }
+}
+
+/// Moves offsets onto another GPU's curve by matching voltages, returning the
+/// curve indices of the points that matched.
+pub fn rebase(points: &[VfPoint], onto: &[(usize, VfPoint)]) -> (Vec<usize>, Vec<VfPoint>) {
+    points.iter().filter_map(|point|
+        onto.iter()
+            .find(|&&(_, ref v)| v.voltage == point.voltage)
+            .map(|&(i, ref v)| (i, VfPoint {
+                voltage: point.voltage,
+                frequency: v.frequency - v.delta + point.delta,
+                delta: point.delta,
+            }))
+    ).unzip()
+}
+
+/// A single voltage point present in either curve of a comparison.
+#[derive(Debug, Clone, Serialize)]
+pub struct PointDiff {
+    pub voltage: Microvolts,
+    pub a: Option<VfPoint>,
+    pub b: Option<VfPoint>,
+    pub delta: Option<KilohertzDelta>,
+    pub frequency: Option<KilohertzDelta>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CurveDiff {
+    pub points: Vec<PointDiff>,
+    pub above: Option<Microvolts>,
+    pub max_gain: Option<KilohertzDelta>,
+    pub average_gain: Option<KilohertzDelta>,
+}
+
+/// Aligns two curves by voltage, gains are measured from `a` to `b`.
+pub fn diff(a: &[VfPoint], b: &[VfPoint], above: Option<Microvolts>) -> CurveDiff {
+    let mut voltages = a.iter().chain(b.iter()).map(|p| p.voltage).collect::<Vec<_>>();
+    voltages.sort();
+    voltages.dedup();
+
+    let points = voltages.into_iter().map(|voltage| {
+        let a = a.iter().find(|p| p.voltage == voltage).cloned();
+        let b = b.iter().find(|p| p.voltage == voltage).cloned();
+        let (delta, frequency) = match (&a, &b) {
+            (&Some(ref a), &Some(ref b)) => (Some(b.delta - a.delta), Some(b.frequency - a.frequency)),
+            _ => (None, None),
+        };
+
+        PointDiff {
+            voltage: voltage,
+            a: a,
+            b: b,
+            delta: delta,
+            frequency: frequency,
+        }
+    }).collect::<Vec<_>>();
+
+    let gains = points.iter()
+        .filter(|p| above.map(|above| p.voltage >= above).unwrap_or(true))
+        .filter_map(|p| p.frequency)
+        .collect::<Vec<_>>();
+
+    CurveDiff {
+        max_gain: gains.iter().cloned().max(),
+        average_gain: if gains.is_empty() {
+            None
+        } else {
+            Some(KilohertzDelta(gains.iter().map(|g| g.0 as i64).sum::<i64>() as i32 / gains.len() as i32))
+        },
+        above: above,
+        points: points,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nvapi::{Kilohertz, KilohertzDelta, Microvolts, Range, VfPoint};
+    use crate::types::CurveFix;
+    use super::{fix, rebase, validate, CurveIssue, CurveLimits};
+
+    /// A point at `voltage` mV running `base` + `delta` MHz.
+    fn point(voltage: u32, base: u32, delta: i32) -> VfPoint {
+        VfPoint {
+            voltage: Microvolts(voltage * 1000),
+            frequency: Kilohertz((base as i32 + delta) as u32 * 1000),
+            delta: KilohertzDelta(delta * 1000),
+        }
+    }
+
+    /// An offset range in MHz and a step in kHz.
+    fn limits(min: i32, max: i32, step: Option<i32>) -> CurveLimits {
+        CurveLimits {
+            range: Some(Range {
+                min: KilohertzDelta(min * 1000),
+                max: KilohertzDelta(max * 1000),
+            }),
+            step: step.map(KilohertzDelta),
+        }
+    }
+
+    #[test]
+    fn non_monotonic() {
+        // listed out of voltage order, the dip is at 850mV
+        let points = [point(900, 1600, 0), point(800, 1500, 0), point(850, 1450, 0)];
+        let issues = validate(&points, &limits(-200, 200, None));
+        assert_eq!(issues.len(), 1);
+        match issues[0] {
+            CurveIssue::NonMonotonic { index, previous, .. } => {
+                assert_eq!(index, 2);
+                assert_eq!(previous, Kilohertz(1500000));
+            },
+            ref issue => panic!("unexpected issue {}", issue),
+        }
+    }
+
+    #[test]
+    fn off_grid_warns() {
+        let points = [point(800, 1500, 20)];
+        let issues = validate(&points, &limits(-200, 200, Some(12500)));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_warning());
+
+        // 12.5MHz bins, as on Pascal
+        let points = [point(800, 1500, 25), point(850, 1600, -50)];
+        assert!(validate(&points, &limits(-200, 200, Some(12500))).is_empty());
+        assert!(validate(&[point(800, 1500, 20)], &limits(-200, 200, None)).is_empty());
+    }
+
+    #[test]
+    fn clamp() {
+        let mut points = [point(800, 1500, 250), point(750, 1550, -300), point(900, 1700, 50)];
+        let limits = limits(-200, 200, None);
+        assert_eq!(validate(&points, &limits).iter().filter(|issue| !issue.is_warning()).count(), 2);
+
+        fix(&mut points, CurveFix::Clamp, &limits);
+        assert_eq!(points[0].delta, KilohertzDelta(200000));
+        assert_eq!(points[0].frequency, Kilohertz(1700000));
+        assert_eq!(points[1].delta, KilohertzDelta(-200000));
+        assert_eq!(points[1].frequency, Kilohertz(1350000));
+        assert_eq!(points[2].delta, KilohertzDelta(50000));
+        assert!(validate(&points, &limits).is_empty());
+    }
+
+    #[test]
+    fn quantize_stays_in_range() {
+        let mut points = [point(800, 1500, 98), point(850, 1700, -98), point(900, 1600, 20)];
+        let limits = limits(-100, 100, Some(15000));
+
+        fix(&mut points, CurveFix::Quantize, &limits);
+        assert_eq!(points[0].delta, KilohertzDelta(90000));
+        assert_eq!(points[0].frequency, Kilohertz(1590000));
+        assert_eq!(points[1].delta, KilohertzDelta(-90000));
+        assert_eq!(points[2].delta, KilohertzDelta(15000));
+        assert!(validate(&points, &limits).is_empty());
+    }
+
+    #[test]
+    fn smooth() {
+        let mut points = [point(800, 1500, 100), point(850, 1520, 0), point(900, 1510, 0), point(950, 1700, 0)];
+        let limits = limits(-200, 200, None);
+
+        fix(&mut points, CurveFix::Smooth, &limits);
+        assert_eq!(points[0].frequency, Kilohertz(1600000));
+        assert_eq!(points[1].frequency, Kilohertz(1600000));
+        assert_eq!(points[1].delta, KilohertzDelta(80000));
+        assert_eq!(points[2].frequency, Kilohertz(1600000));
+        assert_eq!(points[2].delta, KilohertzDelta(90000));
+        assert_eq!(points[3].delta, KilohertzDelta(0));
+        assert!(validate(&points, &limits).is_empty());
+    }
+
+    #[test]
+    fn rebase_by_voltage() {
+        let points = [point(800, 1400, 30), point(825, 1450, 15), point(850, 1500, -15)];
+        let onto = [(4, point(850, 1600, 0)), (3, point(800, 1550, 45))];
+
+        let (indices, points) = rebase(&points, &onto);
+        assert_eq!(indices, [3, 4]);
+        assert_eq!(points[0].delta, KilohertzDelta(30000));
+        assert_eq!(points[0].frequency, Kilohertz(1580000));
+        assert_eq!(points[1].delta, KilohertzDelta(-15000));
+        assert_eq!(points[1].frequency, Kilohertz(1585000));
+    }
+}
@@ -0,0 +1,271 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use nvapi::{Status, GpuStatus, GpuSettings, ClockDomain, ClockLockMode, CoolerControl, CoolerLevel, CoolerPolicy, Microvolts, Percentage};
+use crate::gpu::{GpuHandle, vfp_curve};
+use crate::{chart, range, sample};
+use crate::Error;
+
+/// Samples kept per metric, enough to fill a wide terminal.
+const HISTORY: usize = 512;
+const LABEL_LEN: usize = 28;
+const VALUE_LEN: usize = 12;
+const POWER_STEP: u32 = 1;
+const COOLER_STEP: u32 = 5;
+const KEYS: &'static str = "q quit  tab next GPU  +/- power limit  [/] fan level  a fan auto  </> move lock  u unlock";
+
+/// Raw mode on the alternate screen, restored when dropped.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[derive(Default)]
+struct GpuState {
+    status: Option<GpuStatus>,
+    settings: Option<GpuSettings>,
+    history: Vec<(String, Option<&'static str>, Vec<f64>)>,
+}
+
+struct Dashboard<'a> {
+    gpus: &'a [GpuHandle<'a>],
+    states: Vec<GpuState>,
+    selected: usize,
+    message: Option<String>,
+}
+
+impl<'a> Dashboard<'a> {
+    fn new(gpus: &'a [GpuHandle<'a>]) -> Self {
+        Dashboard {
+            gpus: gpus,
+            states: gpus.iter().map(|_| Default::default()).collect(),
+            selected: 0,
+            message: None,
+        }
+    }
+
+    fn sample(&mut self) -> Result<(), Error> {
+        for (gpu, state) in self.gpus.iter().zip(&mut self.states) {
+            let status = gpu.gpu.status()?;
+
+            for metric in sample::metrics(&status) {
+                let column = metric.column();
+                match state.history.iter_mut().find(|entry| entry.0 == column) {
+                    Some(entry) => entry.2.push(metric.value),
+                    None => state.history.push((column, metric.unit, vec![metric.value])),
+                }
+            }
+            for entry in &mut state.history {
+                let excess = entry.2.len().saturating_sub(HISTORY);
+                entry.2.drain(..excess);
+            }
+
+            state.status = Some(status);
+            state.settings = Some(gpu.gpu.settings()?);
+        }
+
+        Ok(())
+    }
+
+    fn lock_voltage(settings: &GpuSettings) -> Option<Microvolts> {
+        settings.vfp_locks.iter().map(|(_, e)| e)
+            .filter(|&e| e.mode == ClockLockMode::Manual).map(|e| e.voltage).max()
+    }
+
+    fn adjust_power(&self, up: bool) -> Result<String, Error> {
+        let (gpu, state) = (&self.gpus[self.selected], &self.states[self.selected]);
+        let limit = gpu.info.power_limits.first().ok_or(Status::NotSupported)?;
+        let current = state.settings.as_ref().and_then(|s| s.power_limits.first().cloned())
+            .unwrap_or(limit.default);
+
+        let value = Percentage(if up { current.0 + POWER_STEP } else { current.0.saturating_sub(POWER_STEP) });
+        let value = range::check("power limit", value, &limit.range)?;
+        gpu.gpu.set_power_limits(Some(value).into_iter())?;
+
+        Ok(format!("Power limit set to {}", value))
+    }
+
+    fn adjust_cooler(&self, up: bool) -> Result<String, Error> {
+        let (gpu, state) = (&self.gpus[self.selected], &self.states[self.selected]);
+        let current = state.status.as_ref().and_then(|s| s.coolers.first().map(|&(_, ref c)| c.level))
+            .ok_or(Status::NotSupported)?;
+
+        let value = Percentage(if up { current.0 + COOLER_STEP } else { current.0.saturating_sub(COOLER_STEP) });
+        for cooler in gpu.info.coolers.iter().filter(|cooler| matches!(cooler.control, CoolerControl::Variable)) {
+            range::check("cooler level", value, &cooler.range)?;
+        }
+        gpu.gpu.set_cooler_levels(vec![CoolerLevel {
+            policy: CoolerPolicy::Manual,
+            level: value,
+        }].into_iter())?;
+
+        Ok(format!("Cooler level set to {}", value))
+    }
+
+    fn move_lock(&self, up: bool) -> Result<String, Error> {
+        let (gpu, state) = (&self.gpus[self.selected], &self.states[self.selected]);
+        let status = state.status.as_ref().ok_or(Status::NotSupported)?;
+        let settings = state.settings.as_ref().ok_or(Status::NotSupported)?;
+        let vfp = vfp_curve(status, settings, ClockDomain::Graphics)?;
+        if vfp.is_empty() {
+            return Err(Status::NotSupported.into())
+        }
+
+        // start from the current lock, or the point the GPU is running at
+        let current = Self::lock_voltage(settings).or(status.voltage).unwrap_or(vfp[0].1.voltage);
+        let index = vfp.iter().position(|&(_, ref p)| p.voltage >= current).unwrap_or(vfp.len() - 1);
+        let index = if up && index + 1 < vfp.len() {
+            index + 1
+        } else if !up && index > 0 {
+            index - 1
+        } else {
+            return Err("the lock is already at the end of the curve".into())
+        };
+
+        let voltage = vfp[index].1.voltage;
+        gpu.gpu.set_vfp_lock(voltage)?;
+
+        Ok(format!("Locked to {} (point {})", voltage, vfp[index].0))
+    }
+
+    fn key(&mut self, code: KeyCode) -> Result<Option<String>, Error> {
+        Ok(Some(match code {
+            KeyCode::Tab => {
+                self.selected = (self.selected + 1) % self.gpus.len();
+                return Ok(None)
+            },
+            KeyCode::Char('+') | KeyCode::Char('=') => self.adjust_power(true)?,
+            KeyCode::Char('-') => self.adjust_power(false)?,
+            KeyCode::Char(']') => self.adjust_cooler(true)?,
+            KeyCode::Char('[') => self.adjust_cooler(false)?,
+            KeyCode::Char('a') => {
+                self.gpus[self.selected].gpu.reset_cooler_levels()?;
+                "Cooler reset to its default policy".into()
+            },
+            KeyCode::Char('>') | KeyCode::Char('.') => self.move_lock(true)?,
+            KeyCode::Char('<') | KeyCode::Char(',') => self.move_lock(false)?,
+            KeyCode::Char('u') => {
+                self.gpus[self.selected].gpu.reset_vfp_lock()?;
+                "VFP lock removed".into()
+            },
+            _ => return Ok(None),
+        }))
+    }
+
+    fn lines(&self, width: usize, height: usize) -> Vec<String> {
+        let (gpu, state) = (&self.gpus[self.selected], &self.states[self.selected]);
+        let mut lines = vec![format!("GPU {} ({} of {}): {} ({})", gpu.index, self.selected + 1, self.gpus.len(), gpu.info.name, gpu.info.pci)];
+
+        let (status, settings) = match (&state.status, &state.settings) {
+            (&Some(ref status), &Some(ref settings)) => (status, settings),
+            _ => return lines,
+        };
+        let limits = sample::limits(status);
+        lines.push(format!("PState {}  Limits: {}", status.pstate,
+            if limits.is_empty() { "none".into() } else { limits.join(", ") }
+        ));
+        let n_a = || "N/A".to_string();
+        lines.push(format!("Power Limit: {}  Cooler: {}  Lock: {}",
+            settings.power_limits.first().map(|p| p.to_string()).unwrap_or_else(n_a),
+            status.coolers.first().map(|&(_, ref c)| format!("{} {}", c.level, c.policy)).unwrap_or_else(n_a),
+            Self::lock_voltage(settings).map(|v| v.to_string()).unwrap_or_else(n_a),
+        ));
+        lines.push(String::new());
+
+        let spark = width.saturating_sub(LABEL_LEN + VALUE_LEN + 2);
+        for &(ref column, unit, ref values) in &state.history {
+            let value = values.last().cloned().unwrap_or_default();
+            lines.push(format!("{:<label$} {:>value$} {}",
+                column, format!("{:.0} {}", value, unit.unwrap_or("")).trim_end(),
+                chart::sparkline(values, spark),
+                label = LABEL_LEN, value = VALUE_LEN,
+            ));
+        }
+        lines.push(String::new());
+
+        // the chart gets whatever is left after its axis, the message and the key help
+        let chart_height = height.saturating_sub(lines.len() + 3 + 2);
+        if let Ok(vfp) = vfp_curve(status, settings, ClockDomain::Graphics) {
+            if chart_height > 0 {
+                lines.extend(chart::render_vfp(vfp.into_iter(), Self::lock_voltage(settings), status.voltage, width, chart_height));
+            }
+        }
+
+        lines
+    }
+
+    fn draw<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+
+        let mut lines = self.lines(width, height);
+        lines.truncate(height.saturating_sub(2));
+
+        queue!(w, terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(w, cursor::MoveTo(0, row as u16), Print(line.chars().take(width).collect::<String>()))?;
+        }
+        if let Some(ref message) = self.message {
+            queue!(w, cursor::MoveTo(0, height.saturating_sub(2) as u16), Print(message.chars().take(width).collect::<String>()))?;
+        }
+        queue!(w, cursor::MoveTo(0, height.saturating_sub(1) as u16), Print(KEYS.chars().take(width).collect::<String>()))?;
+
+        w.flush()
+    }
+}
+
+pub fn run(gpus: &[GpuHandle], period: Duration) -> Result<(), Error> {
+    let _screen = Screen::enter()?;
+    let mut stdout = io::stdout();
+    let mut dashboard = Dashboard::new(gpus);
+
+    let mut next = Instant::now();
+    loop {
+        if Instant::now() >= next {
+            dashboard.sample()?;
+            dashboard.draw(&mut stdout)?;
+            next = Instant::now() + period;
+        }
+
+        if !event::poll(next.saturating_duration_since(Instant::now()))? {
+            continue
+        }
+
+        match event::read()? {
+            // raw mode swallows Ctrl-C, so treat it like any other quit key
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                code => {
+                    match dashboard.key(code) {
+                        Ok(Some(message)) => dashboard.message = Some(message),
+                        Ok(None) => (),
+                        Err(e) => dashboard.message = Some(e.to_string()),
+                    }
+                    // pick up the new settings right away rather than at the next sample
+                    dashboard.sample()?;
+                    dashboard.draw(&mut stdout)?;
+                },
+            },
+            Event::Resize(..) => dashboard.draw(&mut stdout)?,
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{RootSchema, SchemaObject, SubschemaValidation};
+use serde::{Serialize, Deserialize};
+use nvapi::GpuInfo;
+use crate::events::LimitEvent;
+use crate::monitor::Sample;
+use crate::summary::GpuSummary;
+
+/// Bumped whenever the envelope or record layout changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A GPU's data along with what identifies it, so records can be matched up
+/// without relying on the order GPUs are listed in.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GpuRecord<T> {
+    pub index: usize,
+    pub name: String,
+    pub pci: String,
+    /// Unversioned: this is nvapi-hi's own layout, which `schema_version`
+    /// doesn't cover and which may change along with nvapi-hi.
+    pub data: T,
+}
+
+impl<T> GpuRecord<T> {
+    pub fn new(index: usize, info: &GpuInfo, data: T) -> Self {
+        GpuRecord {
+            index: index,
+            name: info.name.to_string(),
+            pci: info.pci.to_string(),
+            data: data,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Versions {
+    pub driver: String,
+    pub interface: String,
+}
+
+/// Identifies what produced a document, shared by envelopes and monitor samples.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Header {
+    pub schema_version: u32,
+    pub nvoclock_version: String,
+    pub driver_version: String,
+    pub interface_version: String,
+}
+
+impl Header {
+    pub fn new(versions: &Versions) -> Self {
+        Header {
+            schema_version: SCHEMA_VERSION,
+            nvoclock_version: env!("CARGO_PKG_VERSION").into(),
+            driver_version: versions.driver.clone(),
+            interface_version: versions.interface.clone(),
+        }
+    }
+}
+
+/// Structured output of `list`, `info`, `status` and `get`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Envelope<T> {
+    #[serde(flatten)]
+    pub header: Header,
+    /// What `data` holds for each GPU: `descriptor`, `info`, `status` or `settings`.
+    pub kind: String,
+    pub gpus: Vec<GpuRecord<T>>,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(versions: &Versions, kind: &str, gpus: Vec<GpuRecord<T>>) -> Self {
+        Envelope {
+            header: Header::new(versions),
+            kind: kind.into(),
+            gpus: gpus,
+        }
+    }
+}
+
+/// Any document nvoclock writes: an envelope, or a monitor sample, limit
+/// event or summary. The per-GPU `data` comes from `nvapi-hi` and is described
+/// as an opaque, unversioned value, only the records around it are covered.
+pub fn schema() -> RootSchema {
+    let mut gen = SchemaGenerator::default();
+    let documents = vec![
+        gen.subschema_for::<Envelope<serde_json::Value>>(),
+        gen.subschema_for::<Sample<Vec<GpuRecord<serde_json::Value>>>>(),
+        gen.subschema_for::<LimitEvent>(),
+        gen.subschema_for::<Vec<GpuSummary>>(),
+    ];
+
+    RootSchema {
+        meta_schema: gen.settings().meta_schema.clone(),
+        schema: SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(documents),
+                ..Default::default()
+            })),
+            ..Default::default()
+        },
+        definitions: gen.take_definitions(),
+    }
+}
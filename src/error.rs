@@ -1,6 +1,7 @@
 use std::io;
 use std::num::{ParseIntError, ParseFloatError};
 use crate::types::ResetSettings;
+use crate::curve::CurveIssue;
 use quick_error::quick_error;
 use nvapi::{Status, error_message};
 
@@ -21,6 +22,26 @@ quick_error! {
             source(err)
             display("JSON error: {}", err)
         }
+        Yaml(err: serde_yaml::Error) {
+            from()
+            source(err)
+            display("YAML error: {}", err)
+        }
+        TomlSer(err: toml::ser::Error) {
+            from()
+            source(err)
+            display("TOML error: {}", err)
+        }
+        TomlDe(err: toml::de::Error) {
+            from()
+            source(err)
+            display("TOML error: {}", err)
+        }
+        Signal(err: ctrlc::Error) {
+            from()
+            source(err)
+            display("failed to handle interrupts: {}", err)
+        }
         ParseInt(err: ParseIntError) {
             from()
             source(err)
@@ -35,6 +56,19 @@ quick_error! {
             from()
             display("{}", err)
         }
+        Field { path: String, reason: &'static str } {
+            display("field `{}`: {}", path, reason)
+        }
+        Selector { selector: String, reason: String } {
+            display("GPU selector `{}`: {}", selector, reason)
+        }
+        OutOfRange { setting: &'static str, value: String, range: String } {
+            display("{} {} is outside of the allowed {} range", setting, value, range)
+        }
+        Curve(issue: CurveIssue) {
+            from()
+            display("invalid curve: {}", issue)
+        }
         ResetError { setting: ResetSettings, err: Status } {
             from(s: (ResetSettings, Status)) -> {
                 setting: s.0,
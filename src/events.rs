@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use schemars::JsonSchema;
+use nvapi::GpuStatus;
+use crate::monitor::Tick;
+use crate::sample;
+use crate::types::MetricGroup;
+
+/// A perf limit turning on or off, with the readings taken in the same sample.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LimitEvent {
+    pub timestamp: DateTime<Utc>,
+    pub elapsed: f64,
+    pub gpu: usize,
+    pub limit: String,
+    pub active: bool,
+    pub values: BTreeMap<String, f64>,
+}
+
+/// Remembers the limits active at the previous sample of each GPU.
+#[derive(Debug, Default)]
+pub struct LimitTracker {
+    previous: Vec<Vec<String>>,
+}
+
+impl LimitTracker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Limits that changed since the last sample, any active in the first
+    /// sample are reported as having just become active.
+    pub fn update(&mut self, tick: &Tick, gpu: usize, status: &GpuStatus) -> Vec<LimitEvent> {
+        while self.previous.len() <= gpu {
+            self.previous.push(Vec::new());
+        }
+
+        let limits = sample::limits(status);
+        let previous = &self.previous[gpu];
+        let changes = limits.iter().filter(|&l| !previous.contains(l)).map(|l| (l.clone(), true))
+            .chain(previous.iter().filter(|&l| !limits.contains(l)).map(|l| (l.clone(), false)))
+            .collect::<Vec<_>>();
+        self.previous[gpu] = limits;
+
+        if changes.is_empty() {
+            return Vec::new()
+        }
+
+        let values = sample::metrics(status).into_iter()
+            .filter(|m| matches!(m.group, MetricGroup::Clocks | MetricGroup::Voltage | MetricGroup::Sensors))
+            .map(|m| (m.column(), m.value))
+            .collect::<BTreeMap<_, _>>();
+
+        changes.into_iter().map(|(limit, active)| LimitEvent {
+            timestamp: tick.timestamp,
+            elapsed: tick.elapsed.as_secs_f64(),
+            gpu: gpu,
+            limit: limit,
+            active: active,
+            values: values.clone(),
+        }).collect()
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use nvapi::{GpuInfo, ClockDomain, VfPoint};
+use crate::conv::ConvertEnum;
+use crate::types::CurveFormat;
+use crate::{afterburner, plot};
+use crate::Error;
+
+/// Identifies the GPU and driver a curve was exported from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CurveMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pci: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bios_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exported: Option<DateTime<Utc>>,
+}
+
+impl CurveMetadata {
+    pub fn new(info: &GpuInfo, driver_version: &str, domain: ClockDomain) -> Self {
+        CurveMetadata {
+            gpu: Some(info.name.to_string()),
+            pci: Some(info.pci.to_string()),
+            bios_version: Some(info.bios_version.to_string()),
+            driver_version: Some(driver_version.into()),
+            domain: Some(domain.to_str().into()),
+            exported: Some(Utc::now()),
+        }
+    }
+
+    /// Lists the fields that differ from `target`, fields missing from either side are ignored.
+    pub fn mismatches(&self, target: &CurveMetadata) -> Vec<String> {
+        let fields = [
+            ("GPU", &self.gpu, &target.gpu),
+            ("PCI ID", &self.pci, &target.pci),
+            ("BIOS version", &self.bios_version, &target.bios_version),
+            ("driver version", &self.driver_version, &target.driver_version),
+            ("domain", &self.domain, &target.domain),
+        ];
+
+        fields.iter().filter_map(|&(name, curve, target)| match (curve, target) {
+            (&Some(ref curve), &Some(ref target)) if curve != target =>
+                Some(format!("curve {} {} does not match {}", name, curve, target)),
+            _ => None,
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CurveFile {
+    #[serde(default)]
+    pub metadata: CurveMetadata,
+    pub points: Vec<VfPoint>,
+}
+
+pub fn format_from_path(path: &str) -> Option<CurveFormat> {
+    Path::new(path).extension().and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .and_then(|ext| match &ext[..] {
+            "cfg" => Some(CurveFormat::Afterburner),
+            ext => CurveFormat::from_str(ext).ok(),
+        })
+}
+
+/// Guesses the format of a curve file from its contents.
+pub fn detect_format(data: &str) -> CurveFormat {
+    let data = data.trim_start();
+    let first_line = data.lines().next().unwrap_or("");
+
+    if afterburner::detect(data) {
+        CurveFormat::Afterburner
+    } else if data.starts_with('<') {
+        CurveFormat::Svg
+    } else if data.starts_with('{') {
+        CurveFormat::Json
+    } else if data.starts_with('[') || first_line.contains('=') {
+        CurveFormat::Toml
+    } else {
+        CurveFormat::Csv
+    }
+}
+
+pub fn write_csv<W: Write, I: Iterator<Item=VfPoint>>(write: W, points: I, delimiter: u8) -> io::Result<()> {
+    let mut w = csv::WriterBuilder::new().delimiter(delimiter).from_writer(write);
+
+    Ok(for point in points {
+        w.serialize(point)?;
+    })
+}
+
+pub fn write_curve<W: Write>(mut write: W, format: CurveFormat, curve: &CurveFile, delimiter: u8) -> Result<(), Error> {
+    match format {
+        CurveFormat::Csv => write_csv(write, curve.points.iter().cloned(), delimiter)?,
+        CurveFormat::Json => {
+            serde_json::to_writer_pretty(&mut write, curve)?;
+            writeln!(write)?;
+        },
+        CurveFormat::Toml => write.write_all(toml::to_string(curve)?.as_bytes())?,
+        CurveFormat::Afterburner => writeln!(write, "{}={}", afterburner::PROFILE_KEY, afterburner::encode(&curve.points))?,
+        CurveFormat::Svg => plot::vfp_svg(write, &curve.points)?,
+    }
+
+    Ok(())
+}
+
+pub fn read_curve<R: Read>(mut read: R, format: Option<CurveFormat>, delimiter: u8) -> Result<CurveFile, Error> {
+    let mut data = String::new();
+    read.read_to_string(&mut data)?;
+
+    match format.unwrap_or_else(|| detect_format(&data)) {
+        CurveFormat::Csv => {
+            let mut csv = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(data.as_bytes());
+            let points = csv.deserialize().collect::<Result<_, csv::Error>>().map_err(io::Error::from)?;
+
+            Ok(CurveFile {
+                metadata: Default::default(),
+                points: points,
+            })
+        },
+        CurveFormat::Json => serde_json::from_str(&data).map_err(From::from),
+        CurveFormat::Toml => toml::from_str(&data).map_err(From::from),
+        CurveFormat::Afterburner => Ok(CurveFile {
+            metadata: Default::default(),
+            points: afterburner::decode(&data)?,
+        }),
+        CurveFormat::Svg => Err("SVG curves can only be exported".into()),
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use log::warn;
+use nvapi::ClockLockMode;
+use crate::gpu::GpuHandle;
+use crate::sample::{self, key};
+use crate::Error;
+
+const PREFIX: &'static str = "nvoclock";
+const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4";
+/// Requests are served one at a time, so a client that stalls mustn't hold up the rest.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Gauges grouped by metric name, so each gets a single `# TYPE` line.
+#[derive(Default)]
+struct Gauges {
+    gauges: BTreeMap<String, Vec<(String, f64)>>,
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Gauges {
+    fn add(&mut self, name: &str, gpu: &GpuHandle, labels: &[(&str, String)], value: f64) {
+        let labels = [
+            ("gpu", gpu.index.to_string()),
+            ("name", gpu.info.name.to_string()),
+            ("pci", gpu.info.pci.to_string()),
+        ].iter().chain(labels)
+            .map(|&(label, ref value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect::<Vec<_>>().join(",");
+
+        self.gauges.entry(format!("{}_{}", PREFIX, name)).or_insert_with(Vec::new)
+            .push((labels, value));
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, samples) in &self.gauges {
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for &(ref labels, value) in samples {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        }
+        out
+    }
+}
+
+pub fn render(gpus: &[GpuHandle]) -> Result<String, Error> {
+    let mut gauges = Gauges::default();
+
+    for gpu in gpus {
+        let status = gpu.gpu.status()?;
+        let set = gpu.gpu.settings()?;
+
+        for metric in sample::metrics(&status) {
+            gauges.add(&metric.base_name(), gpu, &metric.labels, metric.value);
+        }
+
+        let active = sample::limits(&status);
+        for limit in gpu.info.perf.limits {
+            let limit = limit.to_string();
+            let value = if active.contains(&limit) { 1.0 } else { 0.0 };
+            gauges.add("perf_limit_active", gpu, &[("limit", key(&limit))], value);
+        }
+
+        if let Some(boost) = set.voltage_boost {
+            gauges.add("voltage_boost_percent", gpu, &[], boost.0 as f64);
+        }
+
+        for (i, limit) in set.power_limits.iter().enumerate() {
+            gauges.add("power_limit_percent", gpu, &[("index", i.to_string())], limit.0 as f64);
+        }
+
+        for (i, limit) in set.sensor_limits.iter().enumerate() {
+            gauges.add("thermal_limit_c", gpu, &[("index", i.to_string())], limit.0 as f64);
+        }
+
+        for (id, lock) in &set.vfp_locks {
+            if lock.mode == ClockLockMode::Manual {
+                gauges.add("vfp_lock_voltage_mv", gpu, &[("lock", id.to_string())], lock.voltage.0 as f64 / 1000.0);
+            }
+        }
+    }
+
+    Ok(gauges.render())
+}
+
+fn respond(mut stream: TcpStream, status: &str, content_type: &str, body: &str) -> Result<(), Error> {
+    write!(stream, "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )?;
+
+    Ok(())
+}
+
+fn handle(stream: TcpStream, gpus: &[GpuHandle]) -> Result<(), Error> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // headers are irrelevant, but must be read before responding
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut request = request.split_whitespace();
+    match (request.next(), request.next()) {
+        (Some("GET"), Some("/metrics")) => match render(gpus) {
+            Ok(body) => respond(stream, "200 OK", CONTENT_TYPE, &body),
+            Err(e) => respond(stream, "500 Internal Server Error", "text/plain", &e.to_string()),
+        },
+        (Some("GET"), Some("/")) =>
+            respond(stream, "200 OK", "text/html", "<html><body><a href=\"/metrics\">Metrics</a></body></html>"),
+        _ => respond(stream, "404 Not Found", "text/plain", "not found"),
+    }
+}
+
+/// Accepts `:port` as shorthand for listening on all interfaces.
+pub fn listen_address(listen: &str) -> String {
+    if listen.starts_with(':') {
+        format!("0.0.0.0{}", listen)
+    } else {
+        listen.into()
+    }
+}
+
+/// Connections are handled on this thread since NVAPI handles can't be shared
+/// across threads, the timeouts keep a silent client from blocking others.
+pub fn serve(listener: TcpListener, gpus: &[GpuHandle]) -> Result<(), Error> {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => if let Err(e) = handle(stream, gpus) {
+                warn!("Failed to serve request: {}", e);
+            },
+            Err(e) => warn!("Failed to accept connection: {}", e),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::Error;
+
+/// Names for the value half of the `(description, value)` pairs that some
+/// lists are made of, such as `sensors[0].temperature`. Fields of the
+/// description are reached directly, as in `sensors[0].target`.
+const PAIR_VALUES: &'static [(&'static str, &'static str)] = &[
+    ("sensors", "temperature"),
+    ("coolers", "status"),
+];
+const PAIR_VALUE: &'static str = "value";
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A selector such as `clocks.graphics` or `power[0]`.
+#[derive(Debug, Clone)]
+pub struct FieldPath {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+fn invalid(path: &str, reason: &'static str) -> Error {
+    Error::Field {
+        path: path.into(),
+        reason: reason,
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self, Error> {
+        let mut segments = Vec::new();
+
+        for part in path.split('.') {
+            let (key, mut indices) = match part.find('[') {
+                Some(i) => (&part[..i], &part[i..]),
+                None => (part, ""),
+            };
+            if !key.is_empty() {
+                segments.push(Segment::Key(key.into()));
+            } else if indices.is_empty() {
+                return Err(invalid(path, "empty field name"))
+            }
+
+            while !indices.is_empty() {
+                let end = match (indices.starts_with('['), indices.find(']')) {
+                    (true, Some(end)) => end,
+                    _ => return Err(invalid(path, "unterminated index")),
+                };
+                let index = usize::from_str(&indices[1..end]).map_err(|_| invalid(path, "index is not a number"))?;
+                segments.push(Segment::Index(index));
+                indices = &indices[end + 1..];
+            }
+        }
+
+        Ok(FieldPath {
+            path: path.into(),
+            segments: segments,
+        })
+    }
+}
+
+/// What the value half of a pair in the `collection` list is called.
+pub fn pair_value(collection: Option<&str>) -> &'static str {
+    collection.and_then(|c| PAIR_VALUES.iter().find(|&&(list, _)| normalize(list) == normalize(c)))
+        .map(|&(_, name)| name)
+        .unwrap_or(PAIR_VALUE)
+}
+
+/// Whether an array is a `(description, value)` pair.
+pub fn is_pair(values: &[Value]) -> bool {
+    values.len() == 2 && values[0].is_object()
+}
+
+/// Keys compare ignoring case and separators, so `base_clocks` finds `baseClocks`.
+fn normalize(key: &str) -> String {
+    key.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+fn get_key<'a>(value: &'a Value, key: &str, collection: Option<&str>) -> Option<&'a Value> {
+    match *value {
+        Value::Object(ref map) => map.iter()
+            .find(|&(k, _)| normalize(k) == normalize(key))
+            .map(|(_, v)| v),
+        Value::Array(ref pair) if is_pair(pair) => {
+            let name = pair_value(collection);
+
+            if normalize(key) == normalize(name) {
+                Some(&pair[1])
+            } else {
+                get_key(&pair[0], key, None)
+            }
+        },
+        _ => None,
+    }
+}
+
+impl FieldPath {
+    pub fn select<'a>(&self, value: &'a Value) -> Result<&'a Value, Error> {
+        let mut value = value;
+        let mut collection = None;
+
+        for segment in &self.segments {
+            value = match *segment {
+                Segment::Key(ref key) => {
+                    let next = get_key(value, key, collection).ok_or_else(|| invalid(&self.path, "no such field"))?;
+                    collection = Some(&key[..]);
+                    next
+                },
+                Segment::Index(index) => match *value {
+                    Value::Array(ref values) => values.get(index).ok_or_else(|| invalid(&self.path, "index out of bounds"))?,
+                    _ => return Err(invalid(&self.path, "not a list")),
+                },
+            };
+        }
+
+        Ok(value)
+    }
+}
+
+/// Picks each field out of `data`, keyed by the path that selected it.
+pub fn select<T: Serialize>(fields: &[FieldPath], data: &T) -> Result<Map<String, Value>, Error> {
+    let value = serde_json::to_value(data)?;
+
+    fields.iter().map(|field| Ok((field.path.clone(), field.select(&value)?.clone()))).collect()
+}
+
+/// Strings print without quotes, anything else as compact JSON.
+pub fn bare(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => s.clone(),
+        ref value => value.to_string(),
+    }
+}
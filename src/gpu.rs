@@ -0,0 +1,64 @@
+use std::iter;
+use nvapi::{Status, Gpu, GpuInfo, GpuStatus, GpuSettings, ClockDomain, KilohertzDelta, VfPoint};
+use crate::select;
+use crate::Error;
+
+/// A selected GPU along with the info that identifies it, for the commands
+/// that keep running against the same GPUs.
+pub struct GpuHandle<'a> {
+    pub index: usize,
+    pub gpu: &'a Gpu,
+    pub info: GpuInfo,
+}
+
+/// The GPUs picked by `selectors`, as `select::select` picks them.
+pub fn handles<'a, S: AsRef<str>, I: IntoIterator<Item=S>>(gpus: &'a [Gpu], selectors: Option<I>) -> Result<Vec<GpuHandle<'a>>, Error> {
+    select::select(gpus, selectors)?.into_iter()
+        .map(|(i, gpu)| Ok(GpuHandle {
+            index: i,
+            gpu: gpu,
+            info: gpu.info()?,
+        })).collect()
+}
+
+/// A domain's curve with the offset applied to each point, keyed by curve index.
+pub fn vfp_curve(status: &GpuStatus, settings: &GpuSettings, domain: ClockDomain) -> Result<Vec<(usize, VfPoint)>, Error> {
+    let vfp = status.vfp.as_ref().ok_or(Status::NotSupported)?;
+    let vfp_deltas = settings.vfp.as_ref().ok_or(Status::NotSupported)?;
+    let (points, deltas) = match domain {
+        ClockDomain::Graphics => (&vfp.graphics, &vfp_deltas.graphics),
+        ClockDomain::Memory => (&vfp.memory, &vfp_deltas.memory),
+        _ => return Err("VFP is only available for the graphics and memory domains".into()),
+    };
+
+    if points.len() != deltas.len() {
+        return Err("VFP curve and offsets have a different number of points".into())
+    }
+
+    points.iter().zip(deltas.iter())
+        .map(|((&i0, p), (&i1, d))| if i0 == i1 {
+            Ok((i0, VfPoint::new(p.clone(), d.clone())))
+        } else {
+            Err("VFP curve and offsets don't cover the same points".into())
+        }).collect()
+}
+
+/// Writes offsets to a single domain's curve, leaving the other one as it is.
+pub fn set_vfp_deltas<I: Iterator<Item=(usize, KilohertzDelta)>>(gpu: &Gpu, domain: ClockDomain, deltas: I) -> Result<(), Error> {
+    let deltas = deltas.map(|(i, delta)| (i, delta.into())).collect::<Vec<_>>();
+
+    match domain {
+        ClockDomain::Memory => gpu.inner().set_vfp_table(
+            [0, 0, 0, 0],
+            iter::empty(),
+            deltas.into_iter(),
+        ),
+        _ => gpu.inner().set_vfp_table(
+            [0, 0, 0, 0],
+            deltas.into_iter(),
+            iter::empty(),
+        ),
+    }?;
+
+    Ok(())
+}
@@ -8,6 +8,15 @@ use nvapi::{
     Utilizations, UtilizationDomain,
 };
 use prettytable::{format, row, cell, Table};
+use crate::conv::ConvertEnum;
+use crate::curve::CurveDiff;
+use crate::envelope::GpuRecord;
+use crate::field::{self, FieldPath};
+use crate::events::LimitEvent;
+use crate::monitor::Tick;
+use crate::summary::GpuSummary;
+use crate::types::GpuDescriptor;
+use crate::transaction::Change;
 
 const HEADER_LEN: usize = 20;
 
@@ -40,7 +49,39 @@ fn n_a() -> String {
     "N/A".into()
 }
 
-pub fn print_settings(set: &GpuSettings) {
+pub fn print_tick(tick: &Tick, missed: u64) {
+    pline!("Sample", "{} (+{:.3}s)", tick.sample, tick.elapsed.as_secs_f64());
+    pline!("Timestamp", "{}", tick.timestamp.to_rfc3339());
+    if missed > 0 {
+        pline!("Missed Samples", "{}", missed);
+    }
+}
+
+pub fn print_descriptor(gpu: &GpuDescriptor) {
+    println!("GPU #{}: {}", gpu.index, gpu.name);
+    pline!("PCI Address", "pci:{}", gpu.address);
+    pline!("Stable ID", "id:{}", gpu.id);
+    pline!("PCI ID", "{}", gpu.pci);
+    pline!("BIOS Version", "{}", gpu.bios_version);
+    pline!("Video Memory", "{:.2}", gpu.memory);
+    pline!("VFP Support", "{}", if gpu.vfp { "Yes" } else { "No" });
+    match gpu.state {
+        Some(ref state) => {
+            pline!("VFP Lock", "{}", state.vfp_lock.map(|v| v.to_string()).unwrap_or_else(|| "None".into()));
+            pline!("Modified", "{}", if state.modified.is_empty() { "None".into() } else { state.modified.join(", ") });
+        },
+        None => {
+            pline!("VFP Lock", "Unknown");
+            pline!("Modified", "Unknown");
+        },
+    }
+}
+
+pub fn print_change(change: &Change) {
+    println!("GPU {}: {} {} -> {}", change.gpu, change.setting, change.current, change.new);
+}
+
+pub fn print_settings(set: &GpuSettings, vfp: Option<&[(usize, VfPoint)]>) {
     if let Some(ref boost) = set.voltage_boost {
         pline!("Voltage Boost", "{}", boost);
     }
@@ -59,9 +100,15 @@ pub fn print_settings(set: &GpuSettings) {
     for ov in &set.overvolt {
         pline!("Overvolt", "{}", ov);
     }
-    for (_, lock) in &set.vfp_locks {
-        if lock.mode == ClockLockMode::Manual {
-            pline!("VFP Lock", "{}", lock.voltage);
+    for (id, lock) in &set.vfp_locks {
+        match lock.mode {
+            ClockLockMode::Manual => {
+                let point = vfp.and_then(|vfp| vfp.iter().find(|&&(_, ref p)| p.voltage == lock.voltage))
+                    .map(|&(i, _)| format!(" (point {})", i))
+                    .unwrap_or_default();
+                pline!(format!("VFP Lock {}", id), "{} @ {}{}", lock.mode.to_str(), lock.voltage, point);
+            },
+            mode => pline!(format!("VFP Lock {}", id), "{}", mode.to_str()),
         }
     }
 }
@@ -413,6 +460,31 @@ pub fn print_vfp<I: Iterator<Item=(usize, VfPoint)>>(vfp: I, lock: Option<Microv
     table.print_tty(false);
 }
 
+pub fn print_vfp_diff(diff: &CurveDiff) {
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!["Voltage", "Frequency A", "Frequency B", "Gain", "Offset A", "Offset B", "Offset Change"]);
+
+    fn opt<T: ToString>(v: Option<T>) -> String {
+        v.map(|v| v.to_string()).unwrap_or_else(n_a)
+    }
+
+    for point in &diff.points {
+        table.add_row(row![
+            point.voltage,
+            opt(point.a.as_ref().map(|p| p.frequency)), opt(point.b.as_ref().map(|p| p.frequency)),
+            opt(point.frequency),
+            opt(point.a.as_ref().map(|p| p.delta)), opt(point.b.as_ref().map(|p| p.delta)),
+            opt(point.delta)
+        ]);
+    }
+    table.print_tty(false);
+
+    let above = diff.above.map(|v| format!(" above {}", v)).unwrap_or_default();
+    pline!("Max Gain", "{}{}", opt(diff.max_gain), above);
+    pline!("Average Gain", "{}{}", opt(diff.average_gain), above);
+}
+
 pub fn print_pstates<'a, I: Iterator<Item=(PState, ClockDomain, &'a PStateLimit, Option<KilohertzDelta>)>>(pstates: I, current: Option<PState>) {
     let mut table = Table::new();
     table.set_format(table_format());
@@ -431,3 +503,63 @@ pub fn print_pstates<'a, I: Iterator<Item=(PState, ClockDomain, &'a PStateLimit,
     }
     table.print_tty(false);
 }
+
+pub fn print_summary(summary: &[GpuSummary]) {
+    for gpu in summary {
+        pline!("GPU", "{}", gpu.gpu);
+        pline!("Samples", "{}", gpu.samples);
+
+        let mut metrics = Table::new();
+        metrics.set_format(table_format());
+        metrics.set_titles(row!["Metric", "Min", "Max", "Mean", "P95"]);
+        for metric in &gpu.metrics {
+            metrics.add_row(row![
+                metric.metric,
+                format!("{:.1}", metric.min), format!("{:.1}", metric.max),
+                format!("{:.1}", metric.mean), format!("{:.1}", metric.p95)
+            ]);
+        }
+        metrics.print_tty(false);
+
+        if !gpu.limits.is_empty() {
+            let mut limits = Table::new();
+            limits.set_format(table_format());
+            limits.set_titles(row!["Perf Limit", "Samples", "Share"]);
+            for limit in &gpu.limits {
+                limits.add_row(row![limit.limit, limit.samples, format!("{:.1}%", limit.share * 100.0)]);
+            }
+            limits.print_tty(false);
+        }
+
+        if !gpu.pstates.is_empty() {
+            let mut pstates = Table::new();
+            pstates.set_format(table_format());
+            pstates.set_titles(row!["PState", "Time", "Share"]);
+            for pstate in &gpu.pstates {
+                pstates.add_row(row![pstate.pstate, format!("{:.1}s", pstate.seconds), format!("{:.1}%", pstate.share * 100.0)]);
+            }
+            pstates.print_tty(false);
+        }
+
+        println!();
+    }
+}
+
+pub fn print_limit_event(event: &LimitEvent) {
+    println!("{} (+{:.3}s) GPU {}: {} {} {}",
+        event.timestamp.to_rfc3339(), event.elapsed, event.gpu, event.limit,
+        if event.active { "active" } else { "inactive" },
+        event.values.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join(" ")
+    );
+}
+
+/// Bare values only, one per line in the order the fields were given.
+pub fn print_fields(fields: &[FieldPath], records: &[GpuRecord<serde_json::Map<String, serde_json::Value>>]) {
+    for record in records {
+        for field in fields {
+            if let Some(value) = record.data.get(&field.to_string()) {
+                println!("{}", field::bare(value));
+            }
+        }
+    }
+}
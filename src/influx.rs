@@ -0,0 +1,40 @@
+use std::io::Write;
+use nvapi::{GpuInfo, GpuStatus};
+use crate::monitor::Tick;
+use crate::sample;
+use crate::Error;
+
+const MEASUREMENT: &'static str = "nvoclock";
+
+/// Tag keys and values escape commas, spaces and equals signs.
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+fn escape_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes a single line protocol point for one GPU sample.
+pub fn write_status<W: Write>(mut w: W, tick: &Tick, gpu: usize, info: &GpuInfo, status: &GpuStatus) -> Result<(), Error> {
+    let mut fields = sample::metrics(status).into_iter()
+        .map(|metric| format!("{}={}", metric.column(), metric.value))
+        .collect::<Vec<_>>();
+    fields.push(format!("limits=\"{}\"", escape_string(&sample::limits(status).join("|"))));
+    let timestamp = tick.timestamp.timestamp_nanos_opt()
+        .ok_or("sample timestamp can't be represented in nanoseconds")?;
+
+    writeln!(w, "{},gpu={},name={},pci={} {} {}",
+        MEASUREMENT,
+        gpu,
+        escape_tag(&info.name.to_string()),
+        escape_tag(&info.pci.to_string()),
+        fields.join(","),
+        timestamp,
+    )?;
+
+    // telegraf's exec input reads until the next point, so don't sit on buffered lines
+    w.flush()?;
+
+    Ok(())
+}
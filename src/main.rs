@@ -1,20 +1,41 @@
+mod afterburner;
+mod alert;
 mod auto;
+mod chart;
+mod curve;
+mod dashboard;
+mod envelope;
+mod events;
+mod export;
+mod field;
+mod exporter;
+mod gpu;
 mod human;
+mod influx;
+mod monitor;
+mod output;
+mod mqtt;
+mod plot;
+mod range;
+mod sample;
+mod select;
+mod summary;
+mod transaction;
 mod conv;
 mod error;
 mod types;
 
 use std::collections::BTreeMap;
 use std::process::exit;
-use std::thread::sleep;
 use std::time::Duration;
 use std::str::FromStr;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
 use std::{fs, iter};
+use std::net::TcpListener;
 use nvapi::{
-    Status, Gpu, GpuInfo, GpuSettings,
-    Percentage, Celsius, Kilohertz, KilohertzDelta, Microvolts, VfPoint,
-    ClockDomain, PState, CoolerPolicy, CoolerLevel, ClockLockMode,
+    Status, Gpu, GpuInfo, GpuStatus, GpuSettings,
+    Percentage, Celsius, Kilohertz, KilohertzDelta, Microvolts, MicrovoltsDelta, VfPoint, Range,
+    ClockDomain, PState, CoolerPolicy, CoolerLevel, CoolerControl, ClockLockMode,
     allowable_result
 };
 use log::info;
@@ -22,6 +43,8 @@ use clap::{Arg, App, SubCommand, AppSettings};
 use self::conv::ConvertEnum;
 use self::error::Error;
 use self::types::*;
+use self::transaction::{Transaction, Change, Mode};
+use self::gpu::{vfp_curve, set_vfp_deltas};
 
 fn main() {
     match main_result() {
@@ -37,14 +60,301 @@ fn is_std(str: &str) -> bool {
     str == "-"
 }
 
-fn export_vfp<W: Write, I: Iterator<Item=VfPoint>>(write: W, points: I, delimiter: u8) -> io::Result<()> {
-    let mut w = csv::WriterBuilder::new().delimiter(delimiter).from_writer(write);
+fn curve_format(matches: &clap::ArgMatches, path: &str) -> Result<Option<CurveFormat>, Error> {
+    Ok(match matches.value_of("format").map(CurveFormat::from_str).transpose()? {
+        Some(format) => Some(format),
+        None => export::format_from_path(path),
+    })
+}
+
+fn read_curve(matches: &clap::ArgMatches, input: &str) -> Result<export::CurveFile, Error> {
+    let delimiter = if matches.is_present("tabs") { b'\t' } else { b',' };
+    let format = curve_format(matches, input)?;
+
+    if is_std(input) {
+        export::read_curve(io::stdin(), format, delimiter)
+    } else {
+        export::read_curve(fs::File::open(input)?, format, delimiter)
+    }
+}
+
+fn write_curve(matches: &clap::ArgMatches, output: &str, curve: &export::CurveFile) -> Result<(), Error> {
+    let delimiter = if matches.is_present("tabs") { b'\t' } else { b',' };
+    let format = curve_format(matches, output)?.unwrap_or(CurveFormat::Csv);
+
+    if is_std(output) {
+        export::write_curve(io::stdout(), format, curve, delimiter)
+    } else {
+        export::write_curve(fs::File::create(output)?, format, curve, delimiter)
+    }
+}
+
+fn tabs_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("tabs")
+        .short("t")
+        .long("tabs")
+        .help("Separate columns using tabs")
+}
+
+fn format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .short("F")
+        .long("format")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .possible_values(CurveFormat::possible_values())
+        .help("File format, detected from the file when omitted")
+}
+
+fn fix_arg<'a, 'b>(help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name("fix")
+        .short("f")
+        .long("fix")
+        .value_name("FIX")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .possible_values(CurveFix::possible_values())
+        .help(help)
+}
+
+fn step_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("step")
+        .short("s")
+        .long("step")
+        .value_name("STEP")
+        .takes_value(true)
+        .help("Driver frequency step (MHz), such as 12.5 on Pascal or 15 on later GPUs")
+}
 
-    Ok(for point in points {
-        w.serialize(point)?;
+fn field_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("field")
+        .long("field")
+        .value_name("PATH")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Only print the selected value, such as `clocks.graphics` or `sensors[0].temperature`")
+}
+
+fn fields(matches: &clap::ArgMatches) -> Result<Vec<field::FieldPath>, Error> {
+    matches.values_of("field").map(|fields| fields.map(field::FieldPath::from_str).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+fn write_fields(oformat: OutputFormat, versions: &envelope::Versions, kind: &str, fields: &[field::FieldPath], records: Vec<envelope::GpuRecord<serde_json::Map<String, serde_json::Value>>>) -> Result<(), Error> {
+    match oformat {
+        OutputFormat::Human => human::print_fields(fields, &records),
+        OutputFormat::Influx => unreachable!(),
+        oformat => output::write_data(io::stdout(), oformat, &envelope::Envelope::new(versions, kind, records))?,
+    }
+
+    Ok(())
+}
+
+fn curve_fixes(matches: &clap::ArgMatches) -> Result<Vec<CurveFix>, Error> {
+    let fixes = matches.values_of("fix").map(|fix| fix.map(CurveFix::from_str).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))?;
+    if fixes.contains(&CurveFix::Quantize) && !matches.is_present("step") {
+        return Err("`--fix quantize` needs the driver's `--step`".into())
+    }
+
+    Ok(fixes)
+}
+
+fn curve_limits(info: &GpuInfo, domain: ClockDomain, matches: &clap::ArgMatches) -> Result<curve::CurveLimits, Error> {
+    let step = matches.value_of("step").map(f64::from_str).transpose()?;
+
+    Ok(curve::CurveLimits {
+        range: info.vfp_limits.get(&domain).map(|limit| limit.range.clone()),
+        step: step.map(|step| KilohertzDelta((step * 1000.0).round() as i32)),
     })
 }
 
+/// Identity, capabilities and whatever has been changed from the defaults.
+fn gpu_descriptor(gpu: &Gpu, identity: select::Identity) -> Result<GpuDescriptor, Error> {
+    let info = gpu.info()?;
+    let state = match (gpu.status(), gpu.settings()) {
+        (Ok(status), Ok(set)) => Some(gpu_state(&info, &status, &set)),
+        (Err(e), _) | (_, Err(e)) => {
+            info!("GPU {} state unavailable: {:?}", identity.index, e);
+            None
+        },
+    };
+
+    Ok(GpuDescriptor {
+        index: identity.index,
+        name: identity.name,
+        address: identity.pci.to_string(),
+        id: identity.id,
+        pci: info.pci.to_string(),
+        bios_version: info.bios_version.to_string(),
+        memory: info.memory.dedicated,
+        vfp: !info.vfp_limits.is_empty(),
+        state: state,
+    })
+}
+
+fn gpu_state(info: &GpuInfo, status: &GpuStatus, set: &GpuSettings) -> GpuState {
+    let modified = [
+        (ResetSettings::VoltageBoost, set.voltage_boost.map(|boost| boost.0 != 0).unwrap_or(false)),
+        (ResetSettings::SensorLimits, set.sensor_limits.iter().zip(&info.sensor_limits).any(|(&limit, info)| limit != info.default)),
+        (ResetSettings::PowerLimits, set.power_limits.iter().zip(&info.power_limits).any(|(&limit, info)| limit != info.default)),
+        (ResetSettings::CoolerLevels, status.coolers.iter().zip(&info.coolers).any(|(&(_, ref cooler), info)| cooler.policy != info.default_policy)),
+        (ResetSettings::VfpDeltas, vfp_curve(status, set, ClockDomain::Graphics)
+            .map(|vfp| vfp.iter().any(|&(_, ref p)| p.delta.0 != 0)).unwrap_or(false)),
+        (ResetSettings::VfpLock, set.vfp_locks.values().any(|lock| lock.mode == ClockLockMode::Manual)),
+        (ResetSettings::PStateDeltas, set.pstate_deltas.values().flat_map(|d| d.values()).any(|delta| delta.0 != 0)),
+    ];
+
+    GpuState {
+        vfp_lock: set.vfp_locks.values()
+            .filter(|lock| lock.mode == ClockLockMode::Manual).map(|lock| lock.voltage).max(),
+        modified: modified.iter().filter(|&&(_, modified)| modified)
+            .map(|&(setting, _)| setting.to_str().to_string()).collect(),
+    }
+}
+
+/// Base voltage domains in the order `set_overvolt` writes them, along with
+/// the range of each editable one. Only GPUs before Pascal expose these.
+fn overvolt_domains(gpu: &Gpu) -> nvapi::Result<Vec<Option<Range<MicrovoltsDelta>>>> {
+    let domains = gpu.inner().pstates()?.overvolt.into_iter()
+        .map(|v| if v.editable { Some(v.voltage_delta.range) } else { None })
+        .collect::<Vec<_>>();
+
+    if domains.iter().all(Option::is_none) {
+        Err(Status::NotSupported)
+    } else {
+        Ok(domains)
+    }
+}
+
+/// A value for every domain, `deltas` fill the editable ones in order and the
+/// rest keep their `current` value.
+fn overvolt_values(domains: &[Option<Range<MicrovoltsDelta>>], current: &[MicrovoltsDelta], deltas: &[MicrovoltsDelta]) -> Result<Vec<MicrovoltsDelta>, Error> {
+    if deltas.len() > domains.iter().filter(|range| range.is_some()).count() {
+        return Err("more overvolt values than editable voltage domains".into())
+    }
+
+    let mut deltas = deltas.iter();
+    domains.iter().enumerate().map(|(i, range)| {
+        let current = current.get(i).cloned().unwrap_or(MicrovoltsDelta(0));
+        match *range {
+            Some(ref range) => match deltas.next() {
+                Some(&delta) => range::check("overvolt", delta, range),
+                None => Ok(current),
+            },
+            None => Ok(current),
+        }
+    }).collect()
+}
+
+fn reset_overvolt(gpu: &Gpu) -> nvapi::Result<()> {
+    let domains = overvolt_domains(gpu)?;
+    let current = gpu.settings()?.overvolt;
+    let current = current.iter().cloned().chain(iter::repeat(MicrovoltsDelta(0)));
+
+    gpu.inner().set_overvolt(domains.iter().zip(current)
+        .map(|(range, current)| if range.is_some() { MicrovoltsDelta(0) } else { current })
+    )
+}
+
+fn current_lock(settings: &GpuSettings) -> String {
+    settings.vfp_locks.values()
+        .filter(|lock| lock.mode == ClockLockMode::Manual).map(|lock| lock.voltage).max()
+        .map(|voltage| voltage.to_string()).unwrap_or_else(|| "None".into())
+}
+
+/// Commands that only operate on curve files and never touch NVAPI.
+fn curve_files(matches: &clap::ArgMatches, oformat: OutputFormat) -> Result<i32, Error> {
+    match matches.subcommand() {
+        ("show", Some(matches)) => {
+            let file = read_curve(&matches, matches.value_of("input").unwrap())?;
+            let points = file.points.into_iter().enumerate();
+
+            match oformat {
+                OutputFormat::Human => if matches.is_present("table") {
+                    human::print_vfp(points, None, None)
+                } else {
+                    chart::print_vfp(points, None, None, chart::terminal_width())
+                },
+                OutputFormat::Influx => unreachable!(),
+                oformat => output::write_data(io::stdout(), oformat, &points.map(|(_, point)| point).collect::<Vec<_>>())?,
+            }
+        },
+        ("diff", Some(matches)) => {
+            let above = matches.value_of("above").map(u32::from_str).transpose()?
+                .map(|v| Microvolts(v * 1000));
+            let a = read_curve(&matches, matches.value_of("a").unwrap())?;
+            let b = read_curve(&matches, matches.value_of("b").unwrap())?;
+
+            let diff = curve::diff(&a.points, &b.points, above);
+
+            match oformat {
+                OutputFormat::Human => human::print_vfp_diff(&diff),
+                OutputFormat::Influx => unreachable!(),
+                oformat => output::write_data(io::stdout(), oformat, &diff)?,
+            }
+        },
+        _ => unreachable!("unknown command"),
+    }
+
+    Ok(0)
+}
+
+/// Whether a monitor log line is a sample rather than a `--events` or `--summary` record.
+fn is_sample(value: &serde_json::Value) -> bool {
+    match *value {
+        serde_json::Value::Object(ref record) => record.contains_key("gpus"),
+        serde_json::Value::Array(ref gpus) => !gpus.iter().any(|gpu| gpu.get("metrics").is_some()),
+        _ => false,
+    }
+}
+
+fn plot_log(matches: &clap::ArgMatches) -> Result<i32, Error> {
+    let interval = matches.value_of("interval").map(f64::from_str).unwrap()?;
+    let input = matches.value_of("input").unwrap();
+    let output = matches.value_of("output").unwrap();
+
+    let input: Box<dyn io::BufRead> = if is_std(input) {
+        Box::new(io::BufReader::new(io::stdin()))
+    } else {
+        Box::new(io::BufReader::new(fs::File::open(input)?))
+    };
+
+    let mut samples = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let value = serde_json::from_str::<serde_json::Value>(&line)?;
+        if !is_sample(&value) {
+            continue
+        }
+
+        // older logs hold bare statuses, or are bare arrays without timestamps
+        let sample = if let Ok(sample) = serde_json::from_value::<monitor::Sample<Vec<envelope::GpuRecord<GpuStatus>>>>(value.clone()) {
+            (sample.elapsed, sample.gpus.into_iter().map(|record| (record.index, record.data)).collect())
+        } else if let Ok(sample) = serde_json::from_value::<monitor::Sample<Vec<GpuStatus>>>(value.clone()) {
+            (sample.elapsed, sample.gpus.into_iter().enumerate().collect())
+        } else {
+            let gpus: Vec<GpuStatus> = serde_json::from_value(value)?;
+            (samples.len() as f64 * interval, gpus.into_iter().enumerate().collect())
+        };
+        samples.push(sample);
+    }
+
+    if is_std(output) {
+        plot::log_svg(io::stdout(), &samples)
+    } else {
+        plot::log_svg(fs::File::create(output)?, &samples)
+    }?;
+
+    Ok(0)
+}
+
 fn main_result() -> Result<i32, Error> {
     env_logger::init();
 
@@ -58,7 +368,7 @@ fn main_result() -> Result<i32, Error> {
             .value_name("GPU")
             .takes_value(true)
             .multiple(true)
-            .help("GPU index")
+            .help("GPU index, range (0-2), all, pci:BUS:DEV.FN, name:GLOB or id:ID")
         ).arg(Arg::with_name("oformat")
             .short("O")
             .long("output-format")
@@ -71,8 +381,10 @@ fn main_result() -> Result<i32, Error> {
             .about("List detected GPUs")
         ).subcommand(SubCommand::with_name("info")
             .about("Information about the model and capabilities of the GPU")
+            .arg(field_arg())
         ).subcommand(SubCommand::with_name("status")
             .about("Show current GPU usage, sensor, and clock information")
+            .arg(field_arg())
             .arg(Arg::with_name("all")
                 .short("a")
                 .long("all")
@@ -120,6 +432,16 @@ fn main_result() -> Result<i32, Error> {
                 .default_value(POSSIBLE_BOOL_OFF)
                 .default_value_if("all", None, POSSIBLE_BOOL_ON)
                 .help("Show voltage-frequency chart")
+            ).arg(Arg::with_name("domain")
+                .long("domain")
+                .value_name("DOMAIN")
+                .takes_value(true)
+                .possible_values(&[ClockDomain::Graphics.to_str(), ClockDomain::Memory.to_str()])
+                .default_value(ClockDomain::Graphics.to_str())
+                .help("Clock domain of the voltage-frequency chart")
+            ).arg(Arg::with_name("vfp-chart")
+                .long("vfp-chart")
+                .help("Plot the voltage-frequency curve")
             ).arg(Arg::with_name("pstates")
                 .short("P")
                 .long("pstates")
@@ -135,9 +457,118 @@ fn main_result() -> Result<i32, Error> {
                 .value_name("PERIOD")
                 .takes_value(true)
                 .help("Monitor GPU status over time, optionally accepts period in seconds")
+            ).arg(Arg::with_name("count")
+                .short("n")
+                .long("count")
+                .value_name("COUNT")
+                .takes_value(true)
+                .requires("monitor")
+                .help("Stop monitoring after this many samples")
+            ).arg(Arg::with_name("duration")
+                .short("d")
+                .long("duration")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .requires("monitor")
+                .help("Stop monitoring after this many seconds")
+            ).arg(Arg::with_name("log")
+                .short("l")
+                .long("log")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Append a CSV row per GPU for every sample to a file")
+            ).arg(Arg::with_name("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(MetricGroup::possible_values())
+                .help("Columns to include in the CSV log and summary, defaults to all")
+            ).arg(Arg::with_name("summary")
+                .long("summary")
+                .requires("monitor")
+                .help("Summarize each metric, perf limit and PState once monitoring ends")
+            ).arg(Arg::with_name("events")
+                .long("events")
+                .requires("monitor")
+                .help("Only print perf limits becoming active or inactive, along with clocks, voltage and temperatures")
+            ).arg(Arg::with_name("alerts")
+                .long("alerts")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("monitor")
+                .help("TOML file of alert rules to evaluate against every sample")
+            )
+        ).subcommand(SubCommand::with_name("exporter")
+            .about("Serve GPU status and settings as Prometheus metrics")
+            .arg(Arg::with_name("listen")
+                .short("l")
+                .long("listen")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .default_value(":9835")
+                .help("Address to listen on, `:PORT` listens on all interfaces")
+            )
+        ).subcommand(SubCommand::with_name("dashboard")
+            .about("Full-screen live view of GPU status with controls")
+            .arg(Arg::with_name("period")
+                .short("p")
+                .long("period")
+                .value_name("PERIOD")
+                .takes_value(true)
+                .default_value("1")
+                .help("Time between samples in seconds")
+            )
+        ).subcommand(SubCommand::with_name("mqtt")
+            .about("Publish GPU status to an MQTT broker and accept commands")
+            .arg(Arg::with_name("broker")
+                .short("b")
+                .long("broker")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .required(true)
+                .help("MQTT broker address, the port defaults to 1883")
+            ).arg(Arg::with_name("topic")
+                .short("t")
+                .long("topic")
+                .value_name("PREFIX")
+                .takes_value(true)
+                .default_value("nvoclock")
+                .help("Topic prefix")
+            ).arg(Arg::with_name("period")
+                .short("p")
+                .long("period")
+                .value_name("PERIOD")
+                .takes_value(true)
+                .default_value("5")
+                .help("Time between status updates in seconds")
+            ).arg(Arg::with_name("client-id")
+                .long("client-id")
+                .value_name("ID")
+                .takes_value(true)
+                .default_value("nvoclock")
+                .help("MQTT client identifier")
+            ).arg(Arg::with_name("username")
+                .short("u")
+                .long("username")
+                .value_name("USER")
+                .takes_value(true)
+                .help("MQTT username")
+            ).arg(Arg::with_name("password")
+                .long("password")
+                .value_name("PASSWORD")
+                .takes_value(true)
+                .help("MQTT password")
+            ).arg(Arg::with_name("alerts")
+                .long("alerts")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("TOML file of alert rules to evaluate against every sample")
             )
         ).subcommand(SubCommand::with_name("get")
             .about("Show GPU overclock settings")
+            .arg(field_arg())
         ).subcommand(SubCommand::with_name("reset")
             .about("Restore all overclocking settings")
             .arg(Arg::with_name("setting")
@@ -149,7 +580,11 @@ fn main_result() -> Result<i32, Error> {
             )
         ).subcommand(SubCommand::with_name("set")
             .about("GPU overclocking")
-            .arg(Arg::with_name("vboost")
+            .arg(Arg::with_name("dry-run")
+                .short("n")
+                .long("dry-run")
+                .help("Print the planned changes without applying them")
+            ).arg(Arg::with_name("vboost")
                 .short("V")
                 .long("voltage-boost")
                 .value_name("VBOOST")
@@ -210,24 +645,52 @@ fn main_result() -> Result<i32, Error> {
                 )
             ).subcommand(SubCommand::with_name("vfp")
                 .about("GPU Boost 3.0 voltage-frequency curve")
+                .arg(Arg::with_name("domain")
+                    .short("d")
+                    .long("domain")
+                    .value_name("DOMAIN")
+                    .takes_value(true)
+                    .possible_values(&[ClockDomain::Graphics.to_str(), ClockDomain::Memory.to_str()])
+                    .default_value(ClockDomain::Graphics.to_str())
+                    .help("Clock domain of the curve")
+                )
                 .subcommand(SubCommand::with_name("export")
-                    .about("Export current curve as CSV")
-                    .arg(Arg::with_name("tabs")
-                        .short("t")
-                        .long("tabs")
-                        .help("Separate columns using tabs")
-                    ).arg(Arg::with_name("output")
+                    .about("Export current curve to a file")
+                    .arg(tabs_arg())
+                    .arg(format_arg())
+                    .arg(Arg::with_name("output")
                         .value_name("OUTPUT")
                         .takes_value(true)
                         .default_value("-")
                         .help("Output file path")
                     )
                 ).subcommand(SubCommand::with_name("import")
-                    .about("Import a modified curve from CSV")
-                    .arg(Arg::with_name("tabs")
-                        .short("t")
-                        .long("tabs")
-                        .help("Separate columns using tabs")
+                    .about("Import a modified curve from a file")
+                    .arg(tabs_arg())
+                    .arg(format_arg())
+                    .arg(fix_arg("Correct curve problems before importing, applied in order"))
+                    .arg(step_arg())
+                    .arg(Arg::with_name("force")
+                        .long("force")
+                        .help("Import even if the curve fails validation")
+                    ).arg(Arg::with_name("input")
+                        .value_name("INPUT")
+                        .takes_value(true)
+                        .default_value("-")
+                        .help("Input file path")
+                    )
+                ).subcommand(SubCommand::with_name("check")
+                    .about("Validate a curve against the GPU's limits")
+                    .arg(tabs_arg())
+                    .arg(format_arg())
+                    .arg(fix_arg("Correct curve problems before checking, applied in order"))
+                    .arg(step_arg())
+                    .arg(Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .takes_value(true)
+                        .help("Write the fixed curve to a file")
                     ).arg(Arg::with_name("input")
                         .value_name("INPUT")
                         .takes_value(true)
@@ -239,12 +702,19 @@ fn main_result() -> Result<i32, Error> {
                     .arg(Arg::with_name("point")
                         .value_name("POINT")
                         .takes_value(true)
-                        .required(true)
+                        .required_unless("frequency")
                         .help("Point index to lock at")
                     ).arg(Arg::with_name("voltage")
                         .short("v")
                         .long("voltage")
                         .help("Interpret point as voltage instead of index")
+                    ).arg(Arg::with_name("frequency")
+                        .short("f")
+                        .long("frequency")
+                        .value_name("FREQUENCY")
+                        .takes_value(true)
+                        .conflicts_with_all(&["point", "voltage"])
+                        .help("Lock at the lowest voltage point that reaches this frequency (MHz)")
                     )
                 ).subcommand(SubCommand::with_name("unlock")
                     .about("Remove any existing locks")
@@ -302,120 +772,262 @@ fn main_result() -> Result<i32, Error> {
                     )
                 )
             ).subcommand(SubCommand::with_name("overvolt")
+                .about("Raise base voltages on GPUs older than Pascal")
                 .arg(Arg::with_name("voltage")
                     .value_name("VOLTAGE")
                     .multiple(true)
                     .takes_value(true)
-                    .help("Voltage")
+                    .required(true)
+                    .allow_hyphen_values(true)
+                    .help("Voltage delta (uV) for each editable voltage domain, others are left as they are")
+                )
+            )
+        ).subcommand(SubCommand::with_name("vfp")
+            .about("Inspect saved voltage-frequency curves, no GPU required")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("show")
+                .about("Plot a saved curve")
+                .arg(tabs_arg())
+                .arg(format_arg())
+                .arg(Arg::with_name("table")
+                    .long("table")
+                    .help("Show the curve as a table instead")
+                ).arg(Arg::with_name("input")
+                    .value_name("INPUT")
+                    .takes_value(true)
+                    .default_value("-")
+                    .help("Input file path")
+                )
+            ).subcommand(SubCommand::with_name("diff")
+                .about("Compare two saved curves")
+                .arg(tabs_arg())
+                .arg(format_arg())
+                .arg(Arg::with_name("above")
+                    .short("a")
+                    .long("above")
+                    .value_name("VOLTAGE")
+                    .takes_value(true)
+                    .help("Only include points at or above this voltage (mV) in the summary")
+                ).arg(Arg::with_name("a")
+                    .value_name("A")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Baseline curve file path")
+                ).arg(Arg::with_name("b")
+                    .value_name("B")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Compared curve file path")
                 )
             )
+        ).subcommand(SubCommand::with_name("schema")
+            .about("Print the JSON Schema of structured output, including monitor samples, events and summaries")
+        ).subcommand(SubCommand::with_name("plot")
+            .about("Chart a status log recorded with `status -O json --monitor` as SVG, no GPU required")
+            .arg(Arg::with_name("interval")
+                .short("i")
+                .long("interval")
+                .value_name("PERIOD")
+                .takes_value(true)
+                .default_value("1")
+                .help("Time between samples in seconds, for logs without timestamps")
+            ).arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("OUTPUT")
+                .takes_value(true)
+                .default_value("-")
+                .help("Output file path")
+            ).arg(Arg::with_name("input")
+                .value_name("INPUT")
+                .takes_value(true)
+                .default_value("-")
+                .help("Input file path")
+            )
         ).setting(AppSettings::SubcommandRequiredElseHelp);
 
     let matches = app.get_matches();
 
     let mut exit_code = 0;
 
+    let oformat = matches.value_of("oformat").map(OutputFormat::from_str).unwrap()?;
+
+    match (oformat, matches.subcommand_name()) {
+        (OutputFormat::Influx, Some("status")) => (),
+        (OutputFormat::Influx, _) => return Err("influx output is only supported by status".into()),
+        _ => (),
+    }
+
+    match matches.subcommand() {
+        ("vfp", Some(matches)) => return curve_files(matches, oformat),
+        ("plot", Some(matches)) => return plot_log(matches),
+        ("schema", Some(..)) => {
+            serde_json::to_writer_pretty(io::stdout(), &envelope::schema())?;
+            println!();
+            return Ok(0)
+        },
+        _ => (),
+    }
+
     nvapi::initialize()?;
 
     let driver_version = nvapi::driver_version()?;
     info!("Driver version: {} ({})", driver_version.1, driver_version.0);
-    info!("Interface version: {}", nvapi::interface_version()?);
+    let driver_version = format!("{} ({})", driver_version.1, driver_version.0);
+    let interface_version = nvapi::interface_version()?;
+    info!("Interface version: {}", interface_version);
+    let versions = envelope::Versions {
+        driver: driver_version.clone(),
+        interface: interface_version.to_string(),
+    };
 
     let gpu = matches.values_of("gpu");
 
-    fn single_gpu<'a>(gpus: &[&'a Gpu]) -> Result<&'a Gpu, Error> {
-        let mut gpus = gpus.into_iter();
+    fn single_gpu<'a>(gpus: &[(usize, &'a Gpu)]) -> Result<&'a Gpu, Error> {
+        let mut gpus = gpus.iter();
         gpus.next().ok_or_else(|| Error::from("no GPU selected"))
-            .and_then(|g| match gpus.next() {
-                None => Ok(*g),
+            .and_then(|&(_, g)| match gpus.next() {
+                None => Ok(g),
                 Some(..) => Err(Error::from("multiple GPUs selected")),
             })
     }
 
-    fn select_gpus<'a>(gpus: &'a [Gpu], gpu: Option<clap::Values>) -> Result<Vec<&'a Gpu>, Error> {
-        let v = match gpu {
-            Some(gpu) => {
-                let gpu = gpu.map(usize::from_str).collect::<Result<Vec<_>, _>>()?;
-
-                gpus.iter().enumerate().filter_map(|(i, g)| {
-                    for &gpu in &gpu {
-                        if i == gpu {
-                            return Some(g)
-                        }
-                    }
-
-                    None
-                }).collect::<Vec<_>>()
-            },
-            None => gpus.into_iter().collect(),
-        };
-
-        if v.is_empty() {
-            Err(Error::from(Status::NvidiaDeviceNotFound))
-        } else {
-            Ok(v)
-        }
-    }
-
-    let oformat = matches.value_of("oformat").map(OutputFormat::from_str).unwrap()?;
-
     match matches.subcommand() {
         ("list", Some(..)) => {
-            let gpus = Gpu::enumerate()?
-                .into_iter()
-                .map(|gpu| Ok::<_, Status>(GpuDescriptor {
-                    name: gpu.inner().full_name()?,
-                })).collect::<Result<Vec<_>, _>>()?;
+            let gpus = Gpu::enumerate()?;
+            let descriptors = gpus.iter().enumerate()
+                .map(|(i, gpu)| select::Identity::new(i, gpu).and_then(|identity| gpu_descriptor(gpu, identity)))
+                .collect::<Result<Vec<_>, _>>()?;
 
             match oformat {
-                OutputFormat::Human => for (i, gpu) in gpus.into_iter().enumerate() {
-                    println!("GPU #{}: {}", i, gpu.name);
-                },
-                OutputFormat::Json => {
-                    serde_json::to_writer_pretty(io::stdout(), &gpus)?
+                OutputFormat::Human => for descriptor in &descriptors {
+                    human::print_descriptor(descriptor);
+                    println!();
                 },
+                OutputFormat::Influx => unreachable!(),
+                oformat => output::write_data(io::stdout(), oformat, &envelope::Envelope::new(&versions, "descriptor",
+                    gpus.iter().zip(descriptors)
+                        .map(|(gpu, descriptor)| Ok::<_, Error>(envelope::GpuRecord::new(descriptor.index, &gpu.info()?, descriptor)))
+                        .collect::<Result<Vec<_>, _>>()?
+                ))?,
             }
         },
         ("info", Some(matches)) => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let gpus = select::select(&gpus, gpu)?;
+            let fields = fields(&matches)?;
 
             match oformat {
+                _ if !fields.is_empty() => write_fields(oformat, &versions, "info", &fields,
+                    gpus.into_iter()
+                        .map(|(i, gpu)| gpu.info().map_err(Error::from)
+                            .and_then(|info| Ok(envelope::GpuRecord::new(i, &info, field::select(&fields, &info)?))))
+                        .collect::<Result<Vec<_>, _>>()?
+                )?,
                 OutputFormat::Human => {
 
-                    for gpu in gpus {
+                    for (_, gpu) in gpus {
                         let info = gpu.info()?;
                         human::print_info(&info);
                         println!();
                     }
                 },
-                OutputFormat::Json => {
-                    serde_json::to_writer_pretty(
-                        io::stdout(),
-                        &gpus.into_iter().map(|gpu| gpu.info()).collect::<Result<Vec<_>, _>>()?
-                    )?;
-                },
+                OutputFormat::Influx => unreachable!(),
+                oformat => output::write_data(io::stdout(), oformat, &envelope::Envelope::new(&versions, "info",
+                    gpus.into_iter()
+                        .map(|(i, gpu)| gpu.info().map(|info| envelope::GpuRecord::new(i, &info, info.clone())))
+                        .collect::<Result<Vec<_>, _>>()?
+                ))?,
             }
         },
         ("status", Some(matches)) => {
             const NANOS_IN_SECOND: f64 = 1e9;
 
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let gpus = select::select(&gpus, gpu)?;
             let monitor = matches.value_of("monitor").map(f64::from_str).transpose()?
                 .map(|v| Duration::new(v as u64, (v.fract() * NANOS_IN_SECOND) as u32));
+            let count = matches.value_of("count").map(u64::from_str).transpose()?;
+            let duration = matches.value_of("duration").map(f64::from_str).transpose()?
+                .map(|v| Duration::new(v as u64, (v.fract() * NANOS_IN_SECOND) as u32));
+            let mut schedule = monitor::Schedule::new(monitor, count, duration);
+            let groups = match matches.values_of("columns") {
+                Some(columns) => columns.map(MetricGroup::from_str).collect::<Result<Vec<_>, _>>()?,
+                None => MetricGroup::possible_values_typed().to_vec(),
+            };
+            let mut log = match matches.value_of("log") {
+                // CSV rows would be interleaved with the regular output
+                Some(log) if is_std(log) => return Err("`--log` needs a file, stdout already carries the monitor output".into()),
+                Some(log) => Some(sample::CsvLog::new(Box::new(fs::File::create(log)?) as Box<dyn Write>, groups.clone())),
+                None => None,
+            };
+            let mut summary = if matches.is_present("summary") {
+                schedule.stop_on_interrupt()?;
+                Some(summary::Summary::new(groups))
+            } else {
+                None
+            };
+            let fields = fields(&matches)?;
+            if !fields.is_empty() && matches!(oformat, OutputFormat::Influx) {
+                return Err("fields can't be selected for influx output".into())
+            }
+            let mut events = if matches.is_present("events") {
+                match oformat {
+                    OutputFormat::Influx => return Err("limit events can't be printed as influx output".into()),
+                    _ => Some(events::LimitTracker::new()),
+                }
+            } else {
+                None
+            };
+            let mut alerts = matches.value_of("alerts").map(alert::Alerts::load).transpose()?;
+            let infos = gpus.iter().map(|&(_, gpu)| gpu.info()).collect::<Result<Vec<_>, _>>()?;
 
             loop {
+                let tick = schedule.tick();
+                let statuses = gpus.iter().map(|&(_, gpu)| gpu.status()).collect::<Result<Vec<_>, _>>()?;
+
+                if let Some(ref mut events) = events {
+                    for (&(i, _), status) in gpus.iter().zip(&statuses) {
+                        for event in events.update(&tick, i, status) {
+                            match oformat {
+                                OutputFormat::Human => human::print_limit_event(&event),
+                                OutputFormat::Influx => unreachable!(),
+                                oformat => output::write_record(io::stdout(), oformat, "event", &event)?,
+                            }
+                        }
+                    }
+                }
+
                 match oformat {
+                    // transitions replace the per-sample output
+                    _ if events.is_some() => (),
+                    _ if !fields.is_empty() => {
+                        let records = gpus.iter().zip(infos.iter().zip(&statuses))
+                            .map(|(&(i, _), (info, status))| Ok::<_, Error>(envelope::GpuRecord::new(i, info, field::select(&fields, status)?)))
+                            .collect::<Result<Vec<_>, _>>()?;
+
+                        if monitor.is_some() && !matches!(oformat, OutputFormat::Human) {
+                            output::write_record(io::stdout(), oformat, "sample", &monitor::Sample::new(&versions, &tick, schedule.missed(), records))?;
+                        } else {
+                            write_fields(oformat, &versions, "status", &fields, records)?;
+                        }
+                    },
                     OutputFormat::Human => {
                         let show_status = parse_bool_match(&matches, "status");
                         let show_clocks = parse_bool_match(&matches, "clocks");
                         let show_coolers = parse_bool_match(&matches, "coolers");
                         let show_sensors = parse_bool_match(&matches, "sensors");
                         let show_vfp = parse_bool_match(&matches, "vfp");
+                        let show_vfp_chart = matches.is_present("vfp-chart");
                         let show_pstates = parse_bool_match(&matches, "pstates");
+                        let vfp_domain = matches.value_of("domain").map(ClockDomain::from_str).unwrap()?;
+
+                        if monitor.is_some() {
+                            human::print_tick(&tick, schedule.missed());
+                            println!();
+                        }
 
-                        for &gpu in &gpus {
+                        for (&(_, gpu), status) in gpus.iter().zip(&statuses) {
                             let mut info = None;
                             let mut set = None;
 
@@ -435,12 +1047,12 @@ fn main_result() -> Result<i32, Error> {
                                 Ok(set.get_or_insert(gpu.settings()?))
                             }
 
-                            let status = gpu.status()?;
-
                             if show_status {
                                 human::print_status(&status);
 
-                                human::print_settings(requires_set(gpu, &mut set)?);
+                                let set = requires_set(gpu, &mut set)?;
+                                let vfp = vfp_curve(&status, set, ClockDomain::Graphics).ok();
+                                human::print_settings(set, vfp.as_ref().map(|v| &v[..]));
 
                                 println!();
                             }
@@ -468,20 +1080,25 @@ fn main_result() -> Result<i32, Error> {
                                 );
                             }
 
-                            if show_vfp {
+                            if show_vfp || show_vfp_chart {
                                 let set = requires_set(gpu, &mut set)?;
 
-                                let vfp = status.vfp.as_ref().ok_or(Status::NotSupported)?;
-                                let vfp_deltas = set.vfp.as_ref().ok_or(Status::NotSupported)?;
-                                let lock = set.vfp_locks.iter().map(|(_, e)| e)
-                                    .filter(|&e| e.mode == ClockLockMode::Manual).map(|e| e.voltage).max();
-                                human::print_vfp(vfp.graphics.iter().zip(vfp_deltas.graphics.iter())
-                                    .map(|((i0, p), (i1, d))| {
-                                        assert_eq!(i0, i1);
-                                        (*i0, VfPoint::new(p.clone(), d.clone()))
-                                    }),
-                                    lock, status.voltage
-                                );
+                                let vfp = vfp_curve(&status, set, vfp_domain)?;
+                                let (lock, core) = match vfp_domain {
+                                    // locks and the core voltage only apply to the graphics curve
+                                    ClockDomain::Graphics => (
+                                        set.vfp_locks.iter().map(|(_, e)| e)
+                                            .filter(|&e| e.mode == ClockLockMode::Manual).map(|e| e.voltage).max(),
+                                        status.voltage,
+                                    ),
+                                    _ => (None, None),
+                                };
+                                if show_vfp {
+                                    human::print_vfp(vfp.iter().cloned(), lock, core);
+                                }
+                                if show_vfp_chart {
+                                    chart::print_vfp(vfp.into_iter(), lock, core, chart::terminal_width());
+                                }
                             }
 
                             if show_pstates {
@@ -501,47 +1118,125 @@ fn main_result() -> Result<i32, Error> {
                             println!();
                         }
                     },
-                    OutputFormat::Json => {
-                        let status = &gpus.iter().map(|&gpu| gpu.status()).collect::<Result<Vec<_>, _>>()?;
+                    OutputFormat::Influx => for (&(i, _), (info, status)) in gpus.iter().zip(infos.iter().zip(&statuses)) {
+                        influx::write_status(io::stdout(), &tick, i, info, status)?;
+                    },
+                    oformat => {
+                        let records = gpus.iter().zip(infos.iter().zip(&statuses))
+                            .map(|(&(i, _), (info, status))| envelope::GpuRecord::new(i, info, status))
+                            .collect::<Vec<_>>();
+
                         if monitor.is_some() {
-                            // in monitor mode, newlines separate statuses so can't be pretty
-                            serde_json::to_writer(io::stdout(), status)?;
-                            println!();
+                            // in monitor mode each sample is its own record, so JSON can't be pretty
+                            output::write_record(io::stdout(), oformat, "sample", &monitor::Sample::new(&versions, &tick, schedule.missed(), records))?;
                         } else {
-                            serde_json::to_writer_pretty(io::stdout(), status)?;
+                            output::write_data(io::stdout(), oformat, &envelope::Envelope::new(&versions, "status", records))?;
                         }
                     },
                 }
 
-                if let Some(monitor) = monitor.clone() {
-                    sleep(monitor)
-                } else {
+                if let Some(ref mut log) = log {
+                    for (&(i, _), status) in gpus.iter().zip(&statuses) {
+                        log.write(&tick, i, status)?;
+                    }
+                }
+
+                if let Some(ref mut alerts) = alerts {
+                    for (&(i, _), status) in gpus.iter().zip(&statuses) {
+                        alerts.evaluate(tick.elapsed, i, status);
+                    }
+                }
+
+                if let Some(ref mut summary) = summary {
+                    for (&(i, _), status) in gpus.iter().zip(&statuses) {
+                        summary.add(&tick, i, status);
+                    }
+                }
+
+                if !schedule.wait() {
                     break
                 }
             }
+
+            if let Some(summary) = summary {
+                let summary = summary.summarize();
+                match oformat {
+                    OutputFormat::Human => human::print_summary(&summary),
+                    // keep the summary out of the line protocol stream
+                    OutputFormat::Influx => output::write_record(io::stderr(), OutputFormat::Json, "summary", &summary)?,
+                    oformat => output::write_record(io::stdout(), oformat, "summary", &summary)?,
+                }
+            }
+        },
+        ("exporter", Some(matches)) => {
+            let gpus = Gpu::enumerate()?;
+            let gpus = gpu::handles(&gpus, gpu)?;
+
+            let listen = exporter::listen_address(matches.value_of("listen").unwrap());
+            let listener = TcpListener::bind(&listen)?;
+            info!("Serving metrics on http://{}/metrics", listen);
+
+            exporter::serve(listener, &gpus)?;
+        },
+        ("dashboard", Some(matches)) => {
+            let gpus = Gpu::enumerate()?;
+            let gpus = gpu::handles(&gpus, gpu)?;
+            let period = matches.value_of("period").map(f64::from_str).unwrap()?;
+
+            dashboard::run(&gpus, Duration::from_secs_f64(period))?;
+        },
+        ("mqtt", Some(matches)) => {
+            let gpus = Gpu::enumerate()?;
+            let gpus = gpu::handles(&gpus, gpu)?;
+            let period = matches.value_of("period").map(f64::from_str).unwrap()?;
+            let alerts = matches.value_of("alerts").map(alert::Alerts::load).transpose()?;
+            let prefix = matches.value_of("topic").unwrap().trim_end_matches('/');
+
+            let broker = matches.value_of("broker").unwrap();
+            let broker = if broker.contains(':') {
+                broker.to_string()
+            } else {
+                format!("{}:1883", broker)
+            };
+            let stream = std::net::TcpStream::connect(&broker)?;
+            let mut client = mqtt::Client::connect(stream, &mqtt::Options {
+                client_id: matches.value_of("client-id").unwrap().into(),
+                username: matches.value_of("username").map(From::from),
+                password: matches.value_of("password").map(From::from),
+                status_topic: format!("{}/availability", prefix),
+            })?;
+            info!("Connected to MQTT broker {}", broker);
+
+            mqtt::run(&mut client, prefix, &gpus, Duration::from_secs_f64(period), alerts)?;
         },
-        ("get", Some(..)) => {
+        ("get", Some(matches)) => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let gpus = select::select(&gpus, gpu)?;
+            let fields = fields(&matches)?;
 
             match oformat {
+                _ if !fields.is_empty() => write_fields(oformat, &versions, "settings", &fields,
+                    gpus.into_iter()
+                        .map(|(i, gpu)| Ok::<_, Error>(envelope::GpuRecord::new(i, &gpu.info()?, field::select(&fields, &gpu.settings()?)?)))
+                        .collect::<Result<Vec<_>, _>>()?
+                )?,
                 OutputFormat::Human => {
-                    for gpu in gpus {
+                    for (_, gpu) in gpus {
                         let set = gpu.settings()?;
-                        human::print_settings(&set);
+                        human::print_settings(&set, None);
                     }
                 },
-                OutputFormat::Json => {
-                    serde_json::to_writer_pretty(
-                        io::stdout(),
-                        &gpus.into_iter().map(|gpu| gpu.settings()).collect::<Result<Vec<_>, _>>()?
-                    )?;
-                },
+                OutputFormat::Influx => unreachable!(),
+                oformat => output::write_data(io::stdout(), oformat, &envelope::Envelope::new(&versions, "settings",
+                    gpus.into_iter()
+                        .map(|(i, gpu)| Ok::<_, Error>(envelope::GpuRecord::new(i, &gpu.info()?, gpu.settings()?)))
+                        .collect::<Result<Vec<_>, _>>()?
+                ))?,
             }
         },
         ("reset", Some(matches)) => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let gpus = select::select(&gpus, gpu)?;
 
             let (settings, explicit) = if let Some(reset) = matches.values_of("setting") {
                 (reset.map(ResetSettings::from_str).collect::<Result<_, _>>()?, true)
@@ -556,7 +1251,7 @@ fn main_result() -> Result<i32, Error> {
                 }
             }
 
-            for gpu in gpus {
+            for (_, gpu) in gpus {
                 let info = gpu.info()?;
 
                 for &setting in &settings {
@@ -597,8 +1292,7 @@ fn main_result() -> Result<i32, Error> {
                             )?
                         },
                         ResetSettings::Overvolt => warn_result(
-                            // TODO: reset overvolt
-                            Err(Status::NoImplementation),
+                            reset_overvolt(gpu),
                             setting, explicit
                         )?,
                     }
@@ -607,185 +1301,355 @@ fn main_result() -> Result<i32, Error> {
         },
         ("set", Some(matches)) => {
             let gpus = Gpu::enumerate()?;
-            let gpus = select_gpus(&gpus, gpu)?;
+            let gpus = select::select(&gpus, gpu)?;
+            let dry_run = matches.is_present("dry-run");
+
+            // read up front since both passes need it, and stdin can only be read once
+            let import = match matches.subcommand() {
+                ("vfp", Some(matches)) => match matches.subcommand() {
+                    ("import", Some(matches)) => Some(read_curve(&matches, matches.value_of("input").unwrap())?),
+                    _ => None,
+                },
+                _ => None,
+            };
 
-            for gpu in &gpus {
-                if let Some(vboost) = matches.value_of("vboost").map(u32::from_str).transpose()? {
-                    gpu.set_voltage_boost(Percentage(vboost))?
-                }
+            // every value is checked in a first pass that writes nothing, then
+            // applied as a unit so any failure undoes what was already written
+            let mut apply = |transaction: &mut Transaction| -> Result<(), Error> {
+                for &(i, gpu) in &gpus {
+                    let info = gpu.info()?;
 
-                if let Some(plimit) = matches.values_of("plimit") {
-                    let plimit = plimit.map(u32::from_str).map(|v| v.map(|v| Percentage(v))).collect::<Result<Vec<_>, _>>()?;
-                    gpu.set_power_limits(plimit.into_iter())?
-                }
+                    if let Some(vboost) = matches.value_of("vboost").map(u32::from_str).transpose()? {
+                        let vboost = range::check("voltage boost", Percentage(vboost), &Range { min: Percentage(0), max: Percentage(100) })?;
+                        let current = transaction.settings(i, gpu)?.voltage_boost.map(|v| v.to_string()).unwrap_or_else(|| "N/A".into());
 
-                if let Some(tlimit) = matches.values_of("tlimit") {
-                    let tlimit = tlimit.map(i32::from_str).map(|v| v.map(|v| Celsius(v))).collect::<Result<Vec<_>, _>>()?;
-                    gpu.set_sensor_limits(tlimit.into_iter())?
-                }
-            }
+                        if transaction.change(i, gpu, ResetSettings::VoltageBoost, &[Change::new(i, "voltage boost", current, vboost)])? {
+                            gpu.set_voltage_boost(vboost)?
+                        }
+                    }
 
-            match matches.subcommand() {
-                ("pstate", Some(matches)) => {
-                    for gpu in &gpus {
-                        let pstate = matches.value_of("pstate").map(PState::from_str).unwrap()?;
-                        let clock = matches.value_of("clock").map(ClockDomain::from_str).unwrap()?;
-                        let delta = matches.value_of("delta").map(i32::from_str).unwrap()?;
+                    if let Some(plimit) = matches.values_of("plimit") {
+                        let plimit = plimit.map(u32::from_str).map(|v| v.map(|v| Percentage(v))).collect::<Result<Vec<_>, _>>()?;
+                        if plimit.len() > info.power_limits.len() {
+                            return Err("more power limits than the GPU has".into())
+                        }
+                        for (&limit, info) in plimit.iter().zip(&info.power_limits) {
+                            range::check("power limit", limit, &info.range)?;
+                        }
+                        let current = transaction::list(&transaction.settings(i, gpu)?.power_limits);
 
-                        gpu.inner().set_pstates([(pstate, clock, KilohertzDelta(delta))].iter().cloned())?
+                        if transaction.change(i, gpu, ResetSettings::PowerLimits, &[Change::new(i, "power limit", current, transaction::list(&plimit))])? {
+                            gpu.set_power_limits(plimit.into_iter())?
+                        }
                     }
-                },
-                ("cooler", Some(matches)) => {
-                    for gpu in &gpus {
-                        let mode = matches.value_of("policy").map(CoolerPolicy::from_str).unwrap()?;
-                        let level = matches.value_of("level").map(u32::from_str).unwrap()?;
-
-                        gpu.set_cooler_levels(vec![CoolerLevel {
-                            policy: mode,
-                            level: Percentage(level),
-                        }].into_iter())?
+
+                    if let Some(tlimit) = matches.values_of("tlimit") {
+                        let tlimit = tlimit.map(i32::from_str).map(|v| v.map(|v| Celsius(v))).collect::<Result<Vec<_>, _>>()?;
+                        if tlimit.len() > info.sensor_limits.len() {
+                            return Err("more thermal limits than the GPU has".into())
+                        }
+                        for (&limit, info) in tlimit.iter().zip(&info.sensor_limits) {
+                            range::check("thermal limit", limit, &info.range)?;
+                        }
+                        let current = transaction::list(&transaction.settings(i, gpu)?.sensor_limits);
+
+                        if transaction.change(i, gpu, ResetSettings::SensorLimits, &[Change::new(i, "thermal limit", current, transaction::list(&tlimit))])? {
+                            gpu.set_sensor_limits(tlimit.into_iter())?
+                        }
                     }
-                },
-                ("vfp", Some(matches)) => {
-                    match matches.subcommand() {
-                        ("export", Some(matches)) => {
-                            let gpu = single_gpu(&gpus)?;
-                            let delimiter = if matches.is_present("tabs") { b'\t' } else { b',' };
-                            let output = matches.value_of("output").unwrap();
-
-                            let status = gpu.status()?;
-                            let settings = gpu.settings()?;
-
-                            let points = status.vfp.ok_or(Status::NotSupported)?.graphics
-                                .into_iter().zip(settings.vfp.ok_or(Status::NotSupported)?.graphics.into_iter())
-                                .map(|((i0, point), (i1, delta))| {
-                                    assert_eq!(i0, i1);
-                                    VfPoint::new(point, delta)
-                                });
-
-                            if is_std(output) {
-                                export_vfp(io::stdout(), points, delimiter)
-                            } else {
-                                export_vfp(fs::File::create(output)?, points, delimiter)
-                            }?
-                        },
-                        ("import", Some(matches)) => {
-                            for gpu in &gpus {
-                                let delimiter = if matches.is_present("tabs") { b'\t' } else { b',' };
-                                let input = matches.value_of("input").unwrap();
+                }
+
+                match matches.subcommand() {
+                    ("pstate", Some(matches)) => {
+                        for &(i, gpu) in &gpus {
+                            let pstate = matches.value_of("pstate").map(PState::from_str).unwrap()?;
+                            let clock = matches.value_of("clock").map(ClockDomain::from_str).unwrap()?;
+                            let delta = matches.value_of("delta").map(i32::from_str).unwrap()?;
+
+                            let range = gpu.info()?.pstate_limits.get(&pstate).and_then(|limits| limits.get(&clock))
+                                .and_then(|limit| limit.frequency_delta)
+                                .ok_or(Error::Str("that clock can't be offset in that pstate"))?;
+                            let delta = range::check("pstate offset", KilohertzDelta(delta), &range)?;
+                            let current = transaction.settings(i, gpu)?.pstate_deltas.get(&pstate).and_then(|deltas| deltas.get(&clock))
+                                .map(|delta| delta.to_string()).unwrap_or_else(|| "N/A".into());
+                            let change = Change::new(i, format!("{} @ {} offset", clock, pstate), current, delta);
+
+                            if transaction.change(i, gpu, ResetSettings::PStateDeltas, &[change])? {
+                                gpu.inner().set_pstates([(pstate, clock, delta)].iter().cloned())?
+                            }
+                        }
+                    },
+                    ("cooler", Some(matches)) => {
+                        for &(i, gpu) in &gpus {
+                            let mode = matches.value_of("policy").map(CoolerPolicy::from_str).unwrap()?;
+                            let level = Percentage(matches.value_of("level").map(u32::from_str).unwrap()?);
+
+                            // the level is ignored by the default policy, and only variable coolers have a range
+                            if !matches!(mode, CoolerPolicy::None) {
+                                for cooler in gpu.info()?.coolers.iter().filter(|cooler| matches!(cooler.control, CoolerControl::Variable)) {
+                                    range::check("cooler level", level, &cooler.range)?;
+                                }
+                            }
+                            let current = transaction::list(transaction.settings(i, gpu)?.coolers.iter()
+                                .map(|&(_, ref cooler)| format!("{} {}", cooler.level, cooler.policy)));
+                            let change = Change::new(i, "cooler", current, format!("{} {}", level, mode));
+
+                            if transaction.change(i, gpu, ResetSettings::CoolerLevels, &[change])? {
+                                gpu.set_cooler_levels(vec![CoolerLevel {
+                                    policy: mode,
+                                    level: level,
+                                }].into_iter())?
+                            }
+                        }
+                    },
+                    ("vfp", Some(matches)) => {
+                        let domain = matches.value_of("domain").map(ClockDomain::from_str).unwrap()?;
+
+                        match matches.subcommand() {
+                            // these don't change any settings, so only run once
+                            ("export", Some(..)) | ("check", Some(..)) if transaction.mode() == Mode::Validate => (),
+                            ("export", Some(matches)) => {
+                                let gpu = single_gpu(&gpus)?;
+                                let output = matches.value_of("output").unwrap();
 
                                 let status = gpu.status()?;
-                                let vfp = status.vfp.ok_or(Status::NotSupported)?.graphics;
+                                let settings = gpu.settings()?;
 
-                                fn import<R: io::Read>(read: R, delimiter: u8) -> Result<Vec<VfPoint>, csv::Error> {
-                                    let mut csv = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(read);
-                                    let de = csv.deserialize();
+                                let curve = export::CurveFile {
+                                    metadata: export::CurveMetadata::new(&gpu.info()?, &driver_version, domain),
+                                    points: vfp_curve(&status, &settings, domain)?
+                                        .into_iter().map(|(_, point)| point).collect(),
+                                };
 
-                                    de.collect()
+                                write_curve(&matches, output, &curve)?
+                            },
+                            ("import", Some(matches)) => {
+                                let input = import.as_ref().expect("curve is read before applying");
+                                let fixes = curve_fixes(&matches)?;
+
+                                for &(i, gpu) in &gpus {
+                                    let info = gpu.info()?;
+                                    let limits = curve_limits(&info, domain, &matches)?;
+                                    let vfp = vfp_curve(&gpu.status()?, &gpu.settings()?, domain)?;
+
+                                    // warnings are only printed by the first pass
+                                    let first = transaction.mode() != Mode::Apply;
+                                    let target = export::CurveMetadata::new(&info, &driver_version, domain);
+                                    for mismatch in input.metadata.mismatches(&target).into_iter().filter(|_| first) {
+                                        let _ = writeln!(io::stderr(), "warning: {}", mismatch);
+                                    }
+
+                                    let (indices, mut points) = curve::rebase(&input.points, &vfp);
+
+                                    for &fix in &fixes {
+                                        curve::fix(&mut points, fix, &limits);
+                                    }
+
+                                    let issues = curve::validate(&points, &limits);
+                                    for issue in issues.iter().filter(|_| first) {
+                                        let _ = writeln!(io::stderr(), "{}", issue);
+                                    }
+                                    if issues.iter().any(|issue| !issue.is_warning()) && !matches.is_present("force") {
+                                        return Err("curve failed validation, use --fix or --force".into())
+                                    }
+
+                                    let changes = indices.iter().zip(&points)
+                                        .filter_map(|(index, point)| vfp.iter().find(|&&(i, _)| i == *index)
+                                            .filter(|&&(_, ref current)| current.delta != point.delta)
+                                            .map(|&(_, ref current)| Change::new(i, format!("{} point {}", domain, index), current.delta, point.delta))
+                                        ).collect::<Vec<_>>();
+
+                                    if transaction.change_vfp(i, gpu, domain, &changes)? {
+                                        set_vfp_deltas(gpu, domain, indices.into_iter().zip(points.into_iter().map(|p| p.delta)))?;
+                                    }
+                                }
+                            },
+                            ("check", Some(matches)) => {
+                                let gpu = single_gpu(&gpus)?;
+                                let limits = curve_limits(&gpu.info()?, domain, &matches)?;
+                                let mut file = read_curve(&matches, matches.value_of("input").unwrap())?;
+                                let vfp = vfp_curve(&gpu.status()?, &gpu.settings()?, domain)?;
+
+                                // check the points as `import` would write them onto this GPU's curve
+                                let (indices, points) = curve::rebase(&file.points, &vfp);
+                                let skipped = file.points.len() - indices.len();
+                                file.points = points;
+
+                                for fix in curve_fixes(&matches)? {
+                                    curve::fix(&mut file.points, fix, &limits);
                                 }
 
-                                let input = if is_std(input) {
-                                    import(io::stdin(), delimiter)
-                                } else {
-                                    import(fs::File::open(input)?, delimiter)
-                                }.map_err(io::Error::from)?;
-
-                                gpu.inner().set_vfp_table(
-                                    [0, 0, 0, 0],
-                                    input.into_iter().filter_map(|point|
-                                        vfp.iter()
-                                            .find(|&(_, ref v)| v.voltage == point.voltage)
-                                            .map(|(&i, _)| (i, point.delta.into()))
-                                    ),
-                                    ::std::iter::empty(),
-                                )?;
-                            }
-                        },
-                        ("lock", Some(matches)) => {
-                            for gpu in &gpus {
-                                let point = matches.value_of("point").map(u32::from_str).unwrap()?;
-                                let v = if matches.is_present("voltage") {
-                                    Microvolts(point)
-                                } else {
-                                    gpu.status()?.vfp.ok_or(Status::NotSupported)?.graphics.get(&(point as usize))
-                                        .ok_or(Error::Str("invalid point index"))?
-                                        .voltage
+                                let issues = curve::validate(&file.points, &limits);
+                                if skipped > 0 {
+                                    println!("{} points don't match a voltage on the GPU's curve and won't be imported", skipped);
+                                }
+                                for issue in &issues {
+                                    println!("{}", issue);
+                                }
+
+                                if let Some(output) = matches.value_of("output") {
+                                    write_curve(&matches, output, &file)?
+                                }
+
+                                if issues.iter().any(|issue| !issue.is_warning()) {
+                                    exit_code = 1;
+                                }
+                            },
+                            ("lock", Some(matches)) => {
+                                if let ClockDomain::Memory = domain {
+                                    return Err("VFP locks are only supported for the graphics domain".into())
+                                }
+
+                                let point = matches.value_of("point").map(u32::from_str).transpose()?;
+                                let frequency = matches.value_of("frequency").map(u32::from_str).transpose()?
+                                    .map(|f| Kilohertz(f * 1000));
+
+                                for &(i, gpu) in &gpus {
+                                    let v = match (point, frequency) {
+                                        (_, Some(frequency)) => vfp_curve(&gpu.status()?, &gpu.settings()?, domain)?
+                                            .into_iter().map(|(_, point)| point)
+                                            .filter(|point| point.frequency >= frequency)
+                                            .map(|point| point.voltage).min()
+                                            .ok_or(Error::Str("no point on the curve reaches that frequency"))?,
+                                        (Some(point), None) if matches.is_present("voltage") => {
+                                            let vfp = vfp_curve(&gpu.status()?, &gpu.settings()?, domain)?;
+                                            let range = Range {
+                                                min: vfp.iter().map(|&(_, ref p)| p.voltage).min().ok_or(Status::NotSupported)?,
+                                                max: vfp.iter().map(|&(_, ref p)| p.voltage).max().ok_or(Status::NotSupported)?,
+                                            };
+                                            range::check("lock voltage", Microvolts(point), &range)?
+                                        },
+                                        (Some(point), None) => gpu.status()?.vfp.ok_or(Status::NotSupported)?.graphics.get(&(point as usize))
+                                            .ok_or(Error::Str("invalid point index"))?
+                                            .voltage,
+                                        (None, None) => unreachable!(),
+                                    };
+
+                                    let current = current_lock(transaction.settings(i, gpu)?);
+
+                                    if transaction.change(i, gpu, ResetSettings::VfpLock, &[Change::new(i, "vfp lock", current, v)])? {
+                                        gpu.set_vfp_lock(v)?;
+                                    }
+                                }
+                            },
+                            ("unlock", Some(..)) => {
+                                for &(i, gpu) in &gpus {
+                                    let current = current_lock(transaction.settings(i, gpu)?);
+
+                                    if transaction.change(i, gpu, ResetSettings::VfpLock, &[Change::new(i, "vfp lock", current, "None")])? {
+                                        gpu.reset_vfp_lock()?;
+                                    }
+                                }
+                            },
+                            ("auto", Some(matches)) => {
+                                let gpu = single_gpu(&gpus)?;
+
+                                match transaction.mode() {
+                                    Mode::Validate => return Ok(()),
+                                    Mode::DryRun => return Err("automatic testing can't be a dry run".into()),
+                                    Mode::Apply => (),
+                                }
+
+                                if let ClockDomain::Memory = domain {
+                                    return Err("automatic testing is only supported for the graphics domain".into())
+                                }
+
+                                let end = matches.value_of("end").map(usize::from_str).transpose()?;
+                                let start = matches.value_of("start").map(usize::from_str).unwrap()?;
+                                let step = matches.value_of("step").map(i32::from_str).unwrap()?;
+                                let max = matches.value_of("max").map(u32::from_str).unwrap()?;
+
+                                let status = gpu.status()?;
+                                let vfp = status.vfp.ok_or(Status::NotSupported)?;
+                                let settings = gpu.settings()?;
+                                let vfp_delta = settings.vfp.ok_or(Status::NotSupported)?;
+                                let end = end.unwrap_or(vfp.graphics.iter().map(|(&i, _)| i).max().unwrap());
+
+                                let options = auto::AutoDetectOptions {
+                                    fan_override: matches.is_present("fan"),
+                                    step: KilohertzDelta(step * 1000),
+                                    test: matches.value_of("test").map(|v| v.to_owned()),
+                                    voltage_wait_delay: Duration::from_secs(2),
+                                    max_frequency: Kilohertz(max * 1000),
                                 };
 
-                                gpu.set_vfp_lock(v)?;
-                            }
-                        },
-                        ("unlock", Some(..)) => {
-                            for gpu in &gpus {
-                                gpu.reset_vfp_lock()?;
-                            }
-                        },
-                        ("auto", Some(matches)) => {
-                            let gpu = single_gpu(&gpus)?;
-
-                            let end = matches.value_of("end").map(usize::from_str).transpose()?;
-                            let start = matches.value_of("start").map(usize::from_str).unwrap()?;
-                            let step = matches.value_of("step").map(i32::from_str).unwrap()?;
-                            let max = matches.value_of("max").map(u32::from_str).unwrap()?;
-
-                            let status = gpu.status()?;
-                            let vfp = status.vfp.ok_or(Status::NotSupported)?;
-                            let settings = gpu.settings()?;
-                            let vfp_delta = settings.vfp.ok_or(Status::NotSupported)?;
-                            let end = end.unwrap_or(vfp.graphics.iter().map(|(&i, _)| i).max().unwrap());
-
-                            let options = auto::AutoDetectOptions {
-                                fan_override: matches.is_present("fan"),
-                                step: KilohertzDelta(step * 1000),
-                                test: matches.value_of("test").map(|v| v.to_owned()),
-                                voltage_wait_delay: Duration::from_secs(2),
-                                max_frequency: Kilohertz(max * 1000),
-                            };
-
-                            let mut auto = auto::AutoDetect::new(&gpu, options)?;
-                            let mut results: BTreeMap<usize, VfPoint> = Default::default();
-
-                            auto.test_prepare()?;
-
-                            for (i, point, delta) in (start..end).rev()
-                                .filter_map(|i| vfp.graphics.get(&i).map(|v| (i, v)))
-                                .map(|(i, v)| (i, v, vfp_delta.graphics.get(&i).unwrap()))
-                            {
-                                match auto.test_point(i, point.voltage, point.frequency, *delta) {
-                                    Ok(Some((delta, frequency))) => {
-                                        results.insert(i, VfPoint {
-                                            voltage: point.voltage,
-                                            frequency: frequency,
-                                            delta: delta,
-                                        });
-
-                                        info!("found best point: {:#?}", frequency);
-                                    },
-                                    Ok(None) => (),
-                                    Err(e) => {
-                                        let _ = auto.test_cleanup();
-
-                                        let _ = export_vfp(io::stdout(), results.into_iter().map(|(_, v)| v), b',');
-
-                                        return Err(e)
-                                    },
+                                let mut auto = auto::AutoDetect::new(&gpu, options)?;
+                                let mut results: BTreeMap<usize, VfPoint> = Default::default();
+
+                                auto.test_prepare()?;
+
+                                for (i, point, delta) in (start..end).rev()
+                                    .filter_map(|i| vfp.graphics.get(&i).map(|v| (i, v)))
+                                    .map(|(i, v)| (i, v, vfp_delta.graphics.get(&i).unwrap()))
+                                {
+                                    match auto.test_point(i, point.voltage, point.frequency, *delta) {
+                                        Ok(Some((delta, frequency))) => {
+                                            results.insert(i, VfPoint {
+                                                voltage: point.voltage,
+                                                frequency: frequency,
+                                                delta: delta,
+                                            });
+
+                                            info!("found best point: {:#?}", frequency);
+                                        },
+                                        Ok(None) => (),
+                                        Err(e) => {
+                                            let _ = auto.test_cleanup();
+
+                                            let _ = export::write_csv(io::stdout(), results.into_iter().map(|(_, v)| v), b',');
+
+                                            return Err(e)
+                                        },
+                                    }
                                 }
+
+                                let res = auto.test_cleanup();
+
+                                let io_res = export::write_csv(io::stdout(), results.into_iter().map(|(_, v)| v), b',');
+
+                                let _ = res.and_then(|_| io_res.map_err(From::from))?;
+                            },
+                            _ => unreachable!("unknown command"),
+                        }
+                    },
+                    ("overvolt", Some(matches)) => {
+                        let deltas = matches.values_of("voltage").unwrap()
+                            .map(i32::from_str).map(|v| v.map(MicrovoltsDelta))
+                            .collect::<Result<Vec<_>, _>>()?;
+
+                        for &(i, gpu) in &gpus {
+                            let domains = overvolt_domains(gpu)?;
+                            let current = transaction.settings(i, gpu)?.overvolt.clone();
+                            let values = overvolt_values(&domains, &current, &deltas)?;
+                            let change = Change::new(i, "overvolt", transaction::list(&current), transaction::list(&values));
+
+                            if transaction.change(i, gpu, ResetSettings::Overvolt, &[change])? {
+                                gpu.inner().set_overvolt(values.into_iter())?;
                             }
+                        }
+                    },
+                    ("", ..) => (),
+                    _ => unreachable!("unknown command"),
+                }
+
+                Ok(())
+            };
 
-                            let res = auto.test_cleanup();
+            apply(&mut Transaction::new(if dry_run { Mode::DryRun } else { Mode::Validate }))?;
 
-                            let io_res = export_vfp(io::stdout(), results.into_iter().map(|(_, v)| v), b',');
+            if !dry_run {
+                let mut transaction = Transaction::new(Mode::Apply);
 
-                            let _ = res.and_then(|_| io_res.map_err(From::from))?;
-                        },
-                        _ => unreachable!("unknown command"),
+                if let Err(e) = apply(&mut transaction) {
+                    for rollback in transaction.rollback(&gpus) {
+                        let _ = match rollback.result {
+                            Ok(()) => writeln!(io::stderr(), "GPU {}: rolled back {}", rollback.gpu, rollback.setting.to_str()),
+                            Err(e) => writeln!(io::stderr(), "GPU {}: failed to roll back {}: {}", rollback.gpu, rollback.setting.to_str(), e),
+                        };
                     }
-                },
-                ("overvolt", Some(matches)) => {
-                    unimplemented!()
-                },
-                ("", ..) => (),
-                _ => unreachable!("unknown command"),
+
+                    return Err(e)
+                }
             }
         },
         _ => unreachable!("unknown command"),
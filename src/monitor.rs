@@ -0,0 +1,147 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use crate::envelope::{Header, Versions};
+use crate::Error;
+
+/// When a sample was taken, by both the wall clock and time since monitoring started.
+#[derive(Debug, Clone)]
+pub struct Tick {
+    pub sample: u64,
+    pub timestamp: DateTime<Utc>,
+    pub elapsed: Duration,
+}
+
+/// A timestamped monitor sample as written in JSON monitor mode.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Sample<T> {
+    /// Missing from logs written before samples carried versions.
+    #[serde(flatten)]
+    pub header: Option<Header>,
+    pub timestamp: DateTime<Utc>,
+    pub elapsed: f64,
+    pub sample: u64,
+    pub missed: u64,
+    pub gpus: T,
+}
+
+impl<T> Sample<T> {
+    pub fn new(versions: &Versions, tick: &Tick, missed: u64, gpus: T) -> Self {
+        Sample {
+            header: Some(Header::new(versions)),
+            timestamp: tick.timestamp,
+            elapsed: tick.elapsed.as_secs_f64(),
+            sample: tick.sample,
+            missed: missed,
+            gpus: gpus,
+        }
+    }
+}
+
+/// Fixed-rate sampling: deadlines are measured from the start of monitoring
+/// rather than the end of the previous sample, so collection time doesn't
+/// accumulate as drift.
+pub struct Schedule {
+    start: Instant,
+    period: Option<Duration>,
+    count: Option<u64>,
+    duration: Option<Duration>,
+    sample: u64,
+    collected: u64,
+    missed: u64,
+    interrupted: Option<Arc<AtomicBool>>,
+}
+
+impl Schedule {
+    pub fn new(period: Option<Duration>, count: Option<u64>, duration: Option<Duration>) -> Self {
+        Schedule {
+            start: Instant::now(),
+            period: period,
+            count: count,
+            duration: duration,
+            sample: 0,
+            collected: 0,
+            missed: 0,
+            interrupted: None,
+        }
+    }
+
+    /// Ends monitoring at the next sample on Ctrl-C instead of exiting immediately.
+    pub fn stop_on_interrupt(&mut self) -> Result<(), Error> {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let flag = interrupted.clone();
+        ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst))?;
+        self.interrupted = Some(interrupted);
+
+        Ok(())
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.interrupted.as_ref().map(|i| i.load(Ordering::SeqCst)).unwrap_or(false)
+    }
+
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    pub fn tick(&self) -> Tick {
+        Tick {
+            sample: self.sample,
+            timestamp: Utc::now(),
+            elapsed: self.start.elapsed(),
+        }
+    }
+
+    fn deadline(&self, period: Duration) -> Duration {
+        period * self.sample as u32
+    }
+
+    /// Sleeps until the next sample is due, returns false once monitoring should stop.
+    pub fn wait(&mut self) -> bool {
+        self.collected += 1;
+        if self.count.map(|count| self.collected >= count).unwrap_or(false) {
+            return false
+        }
+
+        let period = match self.period {
+            Some(period) => period,
+            None => return false,
+        };
+        self.sample += 1;
+
+        let elapsed = self.start.elapsed();
+        if elapsed > self.deadline(period) && period > Duration::from_secs(0) {
+            let late = elapsed - self.deadline(period);
+            let missed = (late.as_secs_f64() / period.as_secs_f64()) as u64 + 1;
+            let _ = writeln!(io::stderr(), "warning: sampling fell behind by {:.3}s, skipping {} sample(s)",
+                late.as_secs_f64(), missed);
+            self.missed += missed;
+            self.sample += missed;
+        }
+
+        if self.duration.map(|duration| self.deadline(period) > duration).unwrap_or(false) {
+            return false
+        }
+
+        // sleep in slices so an interrupt doesn't wait out a long period
+        let deadline = self.deadline(period);
+        loop {
+            if self.is_interrupted() {
+                return false
+            }
+
+            let elapsed = self.start.elapsed();
+            if deadline <= elapsed {
+                break
+            }
+            sleep((deadline - elapsed).min(Duration::from_millis(100)));
+        }
+
+        true
+    }
+}
@@ -0,0 +1,558 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use nvapi::{Status, GpuStatus, ClockDomain, CoolerControl, CoolerLevel, CoolerPolicy, Percentage};
+use crate::conv::ConvertEnum;
+use crate::alert::Alerts;
+use crate::gpu::{GpuHandle, vfp_curve, set_vfp_deltas};
+use crate::{curve, export, range, sample};
+use crate::Error;
+
+const PROTOCOL_LEVEL: u8 = 4;
+const KEEP_ALIVE: u16 = 60;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+
+/// A byte stream an MQTT session can run over, such as a broker connection or
+/// an in-memory stand-in.
+pub trait Transport: Read + Write {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        // a zero timeout is rejected rather than treated as non-blocking
+        self.set_read_timeout(timeout.map(|t| t.max(Duration::from_millis(1))))
+    }
+}
+
+pub struct Options {
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Retained `online`/`offline` availability topic.
+    pub status_topic: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Packet {
+    ConnAck { code: u8 },
+    Publish { topic: String, payload: Vec<u8> },
+    SubAck { codes: Vec<u8> },
+    PingResp,
+    Other,
+}
+
+fn encode_length(buf: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break
+        }
+    }
+}
+
+fn encode_str(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s);
+}
+
+fn read_str(data: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    if data.len() < 2 {
+        return Err("truncated MQTT packet".into())
+    }
+    let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+    if data.len() < 2 + len {
+        return Err("truncated MQTT packet".into())
+    }
+    Ok((&data[2..2 + len], &data[2 + len..]))
+}
+
+/// A minimal MQTT 3.1.1 client, limited to QoS 0 publishing and subscriptions.
+pub struct Client<S> {
+    stream: S,
+    buf: Vec<u8>,
+    packet_id: u16,
+    last_sent: Instant,
+}
+
+impl<S: Transport> Client<S> {
+    pub fn connect(stream: S, options: &Options) -> Result<Self, Error> {
+        let mut client = Client {
+            stream: stream,
+            buf: Vec::new(),
+            packet_id: 0,
+            last_sent: Instant::now(),
+        };
+
+        // clean session, with a retained will marking us offline
+        let mut flags = 0x02 | 0x04 | 0x20;
+        let mut body = Vec::new();
+        encode_str(&mut body, b"MQTT");
+        body.push(PROTOCOL_LEVEL);
+        if options.username.is_some() {
+            flags |= 0x80;
+        }
+        if options.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+        encode_str(&mut body, options.client_id.as_bytes());
+        encode_str(&mut body, options.status_topic.as_bytes());
+        encode_str(&mut body, b"offline");
+        if let Some(ref username) = options.username {
+            encode_str(&mut body, username.as_bytes());
+        }
+        if let Some(ref password) = options.password {
+            encode_str(&mut body, password.as_bytes());
+        }
+        client.send(CONNECT, &body)?;
+
+        match client.read(None)? {
+            Some(Packet::ConnAck { code: 0 }) => (),
+            Some(Packet::ConnAck { .. }) => return Err("MQTT broker refused the connection".into()),
+            _ => return Err("expected MQTT CONNACK".into()),
+        }
+
+        client.publish(&options.status_topic, b"online", true)?;
+
+        Ok(client)
+    }
+
+    fn send(&mut self, kind: u8, body: &[u8]) -> Result<(), Error> {
+        let mut packet = vec![kind];
+        encode_length(&mut packet, body.len());
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)?;
+        self.stream.flush()?;
+        self.last_sent = Instant::now();
+
+        Ok(())
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Error> {
+        let mut body = Vec::new();
+        encode_str(&mut body, topic.as_bytes());
+        body.extend_from_slice(payload);
+
+        self.send(PUBLISH | if retain { 0x01 } else { 0 }, &body)
+    }
+
+    pub fn subscribe(&mut self, filter: &str) -> Result<(), Error> {
+        self.packet_id = self.packet_id.wrapping_add(1).max(1);
+
+        let mut body = self.packet_id.to_be_bytes().to_vec();
+        encode_str(&mut body, filter.as_bytes());
+        body.push(0);
+
+        self.send(SUBSCRIBE, &body)
+    }
+
+    pub fn ping(&mut self) -> Result<(), Error> {
+        self.send(PINGREQ, &[])
+    }
+
+    /// How long until a ping is due to keep the session alive.
+    pub fn ping_due(&self) -> Duration {
+        (Duration::from_secs(KEEP_ALIVE as u64) / 2).checked_sub(self.last_sent.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Splits a complete packet off the front of the receive buffer.
+    fn parse(&mut self) -> Result<Option<Packet>, Error> {
+        let mut len = 0;
+        let mut header = 1;
+        loop {
+            let byte = match self.buf.get(header) {
+                Some(&byte) => byte,
+                None => return Ok(None),
+            };
+            len |= ((byte & 0x7f) as usize) << (7 * (header - 1));
+            header += 1;
+            if byte & 0x80 == 0 {
+                break
+            }
+            if header > 4 {
+                return Err("invalid MQTT packet length".into())
+            }
+        }
+        if self.buf.len() < header + len {
+            return Ok(None)
+        }
+
+        let packet = self.buf.drain(..header + len).collect::<Vec<_>>();
+        let (kind, body) = (packet[0], &packet[header..]);
+
+        Ok(Some(match kind & 0xf0 {
+            CONNACK if body.len() >= 2 => Packet::ConnAck { code: body[1] },
+            PUBLISH => {
+                let qos = (kind >> 1) & 0x03;
+                let (topic, mut payload) = read_str(body)?;
+                if qos > 0 {
+                    if payload.len() < 2 {
+                        return Err("truncated MQTT packet".into())
+                    }
+                    if qos == 1 {
+                        self.send(PUBACK, &payload[..2])?;
+                    }
+                    payload = &payload[2..];
+                }
+
+                Packet::Publish {
+                    topic: String::from_utf8_lossy(topic).into_owned(),
+                    payload: payload.to_vec(),
+                }
+            },
+            SUBACK if body.len() >= 2 => Packet::SubAck { codes: body[2..].to_vec() },
+            PINGRESP => Packet::PingResp,
+            _ => Packet::Other,
+        }))
+    }
+
+    /// Waits up to `timeout` for the next packet, or indefinitely if `None`.
+    pub fn read(&mut self, timeout: Option<Duration>) -> Result<Option<Packet>, Error> {
+        loop {
+            if let Some(packet) = self.parse()? {
+                return Ok(Some(packet))
+            }
+
+            self.stream.set_timeout(timeout)?;
+            let mut data = [0u8; 4096];
+            match self.stream.read(&mut data) {
+                Ok(0) => return Err("MQTT broker closed the connection".into()),
+                Ok(len) => self.buf.extend_from_slice(&data[..len]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+                    return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn command(gpu: &GpuHandle, command: &str, payload: &str) -> Result<(), Error> {
+    let info = &gpu.info;
+
+    match command {
+        "power_limit" => {
+            let limit = info.power_limits.first().ok_or(Status::NotSupported)?;
+            let value = Percentage(u32::from_str(payload)?);
+            let value = range::check("power limit", value, &limit.range)?;
+
+            gpu.gpu.set_power_limits(Some(value).into_iter())?
+        },
+        "cooler_level" => if payload == CoolerPolicy::None.to_str() {
+            gpu.gpu.reset_cooler_levels()?
+        } else {
+            let value = Percentage(u32::from_str(payload)?);
+            for cooler in info.coolers.iter().filter(|cooler| matches!(cooler.control, CoolerControl::Variable)) {
+                range::check("cooler level", value, &cooler.range)?;
+            }
+
+            gpu.gpu.set_cooler_levels(vec![CoolerLevel {
+                policy: CoolerPolicy::Manual,
+                level: value,
+            }].into_iter())?
+        },
+        "profile" => {
+            let domain = ClockDomain::Graphics;
+            let file = export::read_curve(payload.as_bytes(), None, b',')?;
+            let vfp = vfp_curve(&gpu.gpu.status()?, &gpu.gpu.settings()?, domain)?;
+            let limits = curve::CurveLimits {
+                range: info.vfp_limits.get(&domain).map(|limit| limit.range.clone()),
+                // the driver rounds offsets onto its own grid
+                step: None,
+            };
+
+            let (indices, points) = curve::rebase(&file.points, &vfp);
+            if let Some(issue) = curve::validate(&points, &limits).into_iter().find(|issue| !issue.is_warning()) {
+                return Err(issue.into())
+            }
+
+            set_vfp_deltas(gpu.gpu, domain, indices.into_iter().zip(points.into_iter().map(|p| p.delta)))?
+        },
+        _ => return Err("unknown MQTT command".into()),
+    }
+
+    Ok(())
+}
+
+/// Splits `{prefix}/{gpu}/set/{command}` into the GPU index and command.
+fn command_topic<'a>(prefix: &str, topic: &'a str) -> Option<(&'a str, &'a str)> {
+    let mut parts = topic.strip_prefix(prefix).and_then(|t| t.strip_prefix('/'))?.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(index), Some("set"), Some(cmd), None) => Some((index, cmd)),
+        _ => None,
+    }
+}
+
+fn publish_status<S: Transport>(client: &mut Client<S>, prefix: &str, gpu: &GpuHandle, status: &GpuStatus) -> Result<(), Error> {
+    let topic = format!("{}/{}", prefix, gpu.index);
+
+    client.publish(&format!("{}/status", topic), &serde_json::to_vec(status)?, false)?;
+    for metric in sample::metrics(status) {
+        client.publish(&format!("{}/{}", topic, metric.column()), metric.value.to_string().as_bytes(), false)?;
+    }
+    client.publish(&format!("{}/limits", topic), sample::limits(status).join("|").as_bytes(), false)?;
+
+    Ok(())
+}
+
+/// Publishes status every `period` and applies commands received on
+/// `{prefix}/{gpu}/set/{command}`, reporting the outcome to `{prefix}/{gpu}/result`.
+pub fn run<S: Transport>(client: &mut Client<S>, prefix: &str, gpus: &[GpuHandle], period: Duration, mut alerts: Option<Alerts>) -> Result<(), Error> {
+    client.subscribe(&format!("{}/+/set/+", prefix))?;
+
+    let start = Instant::now();
+    let mut next = start;
+    loop {
+        if Instant::now() >= next {
+            for gpu in gpus {
+                let status = gpu.gpu.status()?;
+                publish_status(client, prefix, gpu, &status)?;
+                if let Some(ref mut alerts) = alerts {
+                    alerts.evaluate(start.elapsed(), gpu.index, &status);
+                }
+            }
+
+            next += period;
+            if next < Instant::now() {
+                warn!("Publishing fell behind, skipping ahead");
+                next = Instant::now() + period;
+            }
+        }
+
+        if client.ping_due() == Duration::default() {
+            client.ping()?;
+        }
+
+        let timeout = next.saturating_duration_since(Instant::now()).min(client.ping_due());
+        match client.read(Some(timeout))? {
+            Some(Packet::Publish { topic, payload }) => {
+                let payload = String::from_utf8_lossy(&payload);
+                let (index, cmd) = match command_topic(prefix, &topic) {
+                    Some(command) => command,
+                    None => continue,
+                };
+                let gpu = match gpus.iter().find(|gpu| gpu.index.to_string() == index) {
+                    Some(gpu) => gpu,
+                    None => {
+                        warn!("Ignoring command {} for unknown GPU {}", cmd, index);
+                        continue
+                    },
+                };
+
+                let result = match command(gpu, cmd, payload.trim()) {
+                    Ok(()) => {
+                        info!("GPU {}: applied {} {}", gpu.index, cmd, payload.trim());
+                        "ok".to_string()
+                    },
+                    Err(e) => {
+                        warn!("GPU {}: {} failed: {}", gpu.index, cmd, e);
+                        e.to_string()
+                    },
+                };
+                client.publish(&format!("{}/{}/result", prefix, gpu.index), result.as_bytes(), false)?;
+            },
+            Some(Packet::SubAck { codes }) => if codes.iter().any(|&code| code & 0x80 != 0) {
+                return Err("MQTT broker rejected the command subscription".into())
+            },
+            Some(..) | None => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    /// A broker stand-in: reads hand out one queued chunk at a time and then
+    /// time out, writes are collected.
+    #[derive(Default)]
+    struct Memory {
+        input: VecDeque<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Memory {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let chunk = match self.input.pop_front() {
+                Some(chunk) => chunk,
+                None => return Err(io::ErrorKind::WouldBlock.into()),
+            };
+            let len = chunk.len().min(buf.len());
+            buf[..len].copy_from_slice(&chunk[..len]);
+            if len < chunk.len() {
+                self.input.push_front(chunk[len..].to_vec());
+            }
+            Ok(len)
+        }
+    }
+
+    impl Write for Memory {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Memory {
+        fn set_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn client(input: &[&[u8]]) -> Client<Memory> {
+        Client {
+            stream: Memory {
+                input: input.iter().map(|chunk| chunk.to_vec()).collect(),
+                output: Vec::new(),
+            },
+            buf: Vec::new(),
+            packet_id: 0,
+            last_sent: Instant::now(),
+        }
+    }
+
+    fn options() -> Options {
+        Options {
+            client_id: "nvoclock".into(),
+            username: None,
+            password: None,
+            status_topic: "nvoclock/availability".into(),
+        }
+    }
+
+    fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![kind];
+        encode_length(&mut packet, body.len());
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    fn publish(topic: &str, qos: u8, packet_id: u16, payload: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        encode_str(&mut body, topic.as_bytes());
+        if qos > 0 {
+            body.extend_from_slice(&packet_id.to_be_bytes());
+        }
+        body.extend_from_slice(payload);
+        packet(PUBLISH | qos << 1, &body)
+    }
+
+    #[test]
+    fn connect() {
+        let mut stream = Memory::default();
+        stream.input.push_back(vec![CONNACK, 2, 0, 0]);
+
+        let client = Client::connect(stream, &options()).unwrap();
+        let output = &client.stream.output;
+
+        let mut body = Vec::new();
+        encode_str(&mut body, b"MQTT");
+        body.extend_from_slice(&[PROTOCOL_LEVEL, 0x26, 0, KEEP_ALIVE as u8]);
+        encode_str(&mut body, b"nvoclock");
+        encode_str(&mut body, b"nvoclock/availability");
+        encode_str(&mut body, b"offline");
+        let connect = packet(CONNECT, &body);
+        assert_eq!(&output[..connect.len()], &connect[..]);
+
+        let mut online = Vec::new();
+        encode_str(&mut online, b"nvoclock/availability");
+        online.extend_from_slice(b"online");
+        assert_eq!(&output[connect.len()..], &packet(PUBLISH | 0x01, &online)[..]);
+    }
+
+    #[test]
+    fn connect_refused() {
+        let mut stream = Memory::default();
+        stream.input.push_back(vec![CONNACK, 2, 0, 5]);
+
+        assert!(Client::connect(stream, &options()).is_err());
+    }
+
+    #[test]
+    fn subscribe() {
+        let mut client = client(&[&[SUBACK, 3, 0, 1, 0]]);
+        client.subscribe("nvoclock/+/set/+").unwrap();
+
+        let mut body = vec![0, 1];
+        encode_str(&mut body, b"nvoclock/+/set/+");
+        body.push(0);
+        assert_eq!(client.stream.output, packet(SUBSCRIBE, &body));
+
+        match client.read(None).unwrap() {
+            Some(Packet::SubAck { codes }) => assert_eq!(codes, vec![0]),
+            packet => panic!("expected SUBACK, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn set_command() {
+        let publish = publish("nvoclock/0/set/power_limit", 1, 0x1234, b"90");
+        let mut client = client(&[&publish]);
+
+        let (topic, payload) = match client.read(None).unwrap() {
+            Some(Packet::Publish { topic, payload }) => (topic, payload),
+            packet => panic!("expected PUBLISH, got {:?}", packet),
+        };
+        assert_eq!(command_topic("nvoclock", &topic), Some(("0", "power_limit")));
+        assert_eq!(payload, b"90");
+        // QoS 1 is acknowledged with the packet id
+        assert_eq!(client.stream.output, vec![PUBACK, 2, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn command_topics() {
+        assert_eq!(command_topic("nvoclock", "nvoclock/1/set/profile"), Some(("1", "profile")));
+        assert_eq!(command_topic("nvoclock", "nvoclock/1/result"), None);
+        assert_eq!(command_topic("nvoclock", "nvoclock/1/set/profile/x"), None);
+        assert_eq!(command_topic("nvoclock", "other/1/set/profile"), None);
+    }
+
+    #[test]
+    fn partial_packets() {
+        // 200 bytes of payload needs a two byte remaining length
+        let payload = vec![b'x'; 200];
+        let publish = publish("nvoclock/0/set/profile", 0, 0, &payload);
+        assert_eq!(publish[1] & 0x80, 0x80);
+
+        let mut client = client(&[&publish[..2]]);
+        assert!(client.read(Some(Duration::from_millis(1))).unwrap().is_none());
+
+        client.stream.input.push_back(publish[2..100].to_vec());
+        client.stream.input.push_back(publish[100..].to_vec());
+        client.stream.input.push_back(vec![PINGRESP, 0]);
+        match client.read(None).unwrap() {
+            Some(Packet::Publish { payload: p, .. }) => assert_eq!(p, payload),
+            packet => panic!("expected PUBLISH, got {:?}", packet),
+        }
+        assert!(matches!(client.read(None).unwrap(), Some(Packet::PingResp)));
+    }
+
+    #[test]
+    fn invalid_length() {
+        let mut client = client(&[&[PUBLISH, 0xff, 0xff, 0xff, 0xff, 0x01]]);
+        assert!(client.read(None).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::Write;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::conv::ConvertEnum;
+use crate::field;
+use crate::types::OutputFormat;
+use crate::Error;
+
+/// Reshapes data into something TOML can hold: it has no nulls, map keys
+/// must be strings, and arrays can't mix tables with plain values, so lists
+/// of `(description, value)` pairs become tables as `--field` addresses them.
+fn toml_value(value: Value, collection: Option<&str>) -> Value {
+    let is_pair = |v: &Value| matches!(*v, Value::Array(ref pair) if field::is_pair(pair));
+
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter()
+            .filter(|&(_, ref v)| !v.is_null())
+            .map(|(k, v)| {
+                let v = toml_value(v, Some(&k));
+                (k, v)
+            }).collect()),
+        Value::Array(values) if !values.is_empty() && values.iter().all(is_pair) => Value::Array(values.into_iter()
+            .map(|pair| pair_table(pair, collection))
+            .collect()),
+        Value::Array(values) => Value::Array(values.into_iter()
+            .filter(|v| !v.is_null())
+            .map(|v| toml_value(v, collection))
+            .collect()),
+        value => value,
+    }
+}
+
+/// The description's fields, plus the value named like `--field` names it.
+fn pair_table(pair: Value, collection: Option<&str>) -> Value {
+    let (desc, value) = match pair {
+        Value::Array(pair) => {
+            let mut pair = pair.into_iter();
+            (pair.next().unwrap_or(Value::Null), pair.next().unwrap_or(Value::Null))
+        },
+        value => (value, Value::Null),
+    };
+    let mut table = match toml_value(desc, None) {
+        Value::Object(table) => table,
+        _ => Map::new(),
+    };
+    if !value.is_null() {
+        table.insert(field::pair_value(collection).into(), toml_value(value, None));
+    }
+
+    Value::Object(table)
+}
+
+fn to_toml<T: Serialize>(value: &T) -> Result<toml::Value, Error> {
+    Ok(toml::Value::try_from(toml_value(serde_json::to_value(value)?, None))?)
+}
+
+/// TOML documents must be tables, so lists are nested under `key`.
+fn toml_document<T: Serialize>(key: &str, value: &T) -> Result<String, Error> {
+    // converting to a `Value` first orders plain values ahead of nested tables
+    let value = match to_toml(value)? {
+        table @ toml::Value::Table(..) => table,
+        value => {
+            let mut table = toml::value::Table::new();
+            table.insert(key.into(), value);
+            toml::Value::Table(table)
+        },
+    };
+
+    Ok(toml::to_string(&value)?)
+}
+
+/// Writes a single document in one of the structured output formats.
+pub fn write_data<W: Write, T: Serialize>(mut w: W, oformat: OutputFormat, value: &T) -> Result<(), Error> {
+    match oformat {
+        OutputFormat::Json => serde_json::to_writer_pretty(w, value)?,
+        OutputFormat::Ndjson => {
+            serde_json::to_writer(&mut w, value)?;
+            writeln!(w)?;
+        },
+        OutputFormat::Yaml => serde_yaml::to_writer(w, value)?,
+        OutputFormat::Toml => write!(w, "{}", toml_document("gpu", value)?)?,
+        OutputFormat::Human | OutputFormat::Influx => unreachable!("{} is not a data format", oformat.to_str()),
+    }
+
+    Ok(())
+}
+
+/// Writes one record of a stream such as monitor samples, so that the output
+/// as a whole stays parseable: JSON as one record per line, YAML as a document
+/// per record, and TOML as an array of tables named `key`.
+pub fn write_record<W: Write, T: Serialize>(mut w: W, oformat: OutputFormat, key: &str, value: &T) -> Result<(), Error> {
+    match oformat {
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            serde_json::to_writer(&mut w, value)?;
+            writeln!(w)?;
+        },
+        OutputFormat::Yaml => serde_yaml::to_writer(&mut w, value)?,
+        OutputFormat::Toml => {
+            let records = match to_toml(value)? {
+                toml::Value::Array(records) => records,
+                record => vec![record],
+            };
+            let mut table = toml::value::Table::new();
+            table.insert(key.into(), toml::Value::Array(records));
+            write!(w, "{}", toml::to_string(&table)?)?;
+        },
+        OutputFormat::Human | OutputFormat::Influx => unreachable!("{} is not a data format", oformat.to_str()),
+    }
+    w.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serde::Serialize;
+    use crate::types::OutputFormat;
+    use super::{write_data, write_record};
+
+    #[derive(Serialize)]
+    struct Desc {
+        kind: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Cooler {
+        level: u32,
+    }
+
+    /// Shaped like the parts of `GpuStatus` that TOML can't hold as-is.
+    #[derive(Serialize)]
+    struct Status {
+        pstate: &'static str,
+        voltage: Option<u32>,
+        sensors: Vec<(Desc, i32)>,
+        coolers: Vec<(Desc, Cooler)>,
+        vfp: BTreeMap<usize, u32>,
+    }
+
+    fn status() -> Status {
+        Status {
+            pstate: "P0",
+            voltage: None,
+            sensors: vec![(Desc { kind: "gpu" }, 50), (Desc { kind: "memory" }, 60)],
+            coolers: vec![(Desc { kind: "fan" }, Cooler { level: 40 })],
+            vfp: vec![(0, 700000), (1, 712500)].into_iter().collect(),
+        }
+    }
+
+    fn parse(output: Vec<u8>) -> toml::Value {
+        toml::from_str(&String::from_utf8(output).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn toml_data_parses_back() {
+        let mut output = Vec::new();
+        write_data(&mut output, OutputFormat::Toml, &vec![status(), status()]).unwrap();
+
+        let value = parse(output);
+        let gpus = value["gpu"].as_array().unwrap();
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0]["sensors"][1]["kind"].as_str(), Some("memory"));
+        assert_eq!(gpus[0]["sensors"][1]["temperature"].as_integer(), Some(60));
+        assert_eq!(gpus[0]["vfp"]["1"].as_integer(), Some(712500));
+        assert_eq!(gpus[1]["coolers"][0]["status"]["level"].as_integer(), Some(40));
+        assert!(gpus[0].get("voltage").is_none());
+    }
+
+    #[test]
+    fn toml_records_parse_back() {
+        let mut output = Vec::new();
+        write_record(&mut output, OutputFormat::Toml, "sample", &status()).unwrap();
+        write_record(&mut output, OutputFormat::Toml, "sample", &status()).unwrap();
+
+        let value = parse(output);
+        let samples = value["sample"].as_array().unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1]["sensors"][0]["temperature"].as_integer(), Some(50));
+    }
+}
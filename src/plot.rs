@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use nvapi::{GpuStatus, VfPoint};
+
+const WIDTH: f64 = 800.0;
+const PANEL_HEIGHT: f64 = 240.0;
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_RIGHT: f64 = 150.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+const TICKS: usize = 5;
+const COLORS: &'static [&'static str] = &[
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+pub struct Panel {
+    pub title: String,
+    pub unit: &'static str,
+    pub series: Vec<Series>,
+}
+
+impl Panel {
+    fn from_map(title: &str, unit: &'static str, series: BTreeMap<String, Vec<(f64, f64)>>) -> Self {
+        Panel {
+            title: title.into(),
+            unit: unit,
+            series: series.into_iter().map(|(name, points)| Series {
+                name: name,
+                points: points,
+            }).collect(),
+        }
+    }
+
+    fn bounds(&self) -> Option<((f64, f64), (f64, f64))> {
+        let mut points = self.series.iter().flat_map(|s| s.points.iter()).peekable();
+        points.peek()?;
+
+        Some(points.fold(((f64::MAX, f64::MIN), (f64::MAX, f64::MIN)), |((xmin, xmax), (ymin, ymax)), &(x, y)|
+            ((xmin.min(x), xmax.max(x)), (ymin.min(y), ymax.max(y)))
+        ))
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Widens an empty range so flat lines still get a usable axis.
+fn pad_range((min, max): (f64, f64)) -> (f64, f64) {
+    if max > min {
+        (min, max)
+    } else {
+        (min - 1.0, max + 1.0)
+    }
+}
+
+fn write_panel<W: Write>(w: &mut W, panel: &Panel, top: f64, x_label: &str) -> io::Result<()> {
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = PANEL_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let (left, bottom) = (MARGIN_LEFT, top + MARGIN_TOP + plot_height);
+
+    writeln!(w, r##"<text x="{}" y="{}" font-weight="bold">{}</text>"##, left, top + MARGIN_TOP - 12.0, escape(&panel.title))?;
+
+    let (xrange, yrange) = match panel.bounds() {
+        Some(bounds) => bounds,
+        None => return Ok(()),
+    };
+    let (xmin, xmax) = pad_range(xrange);
+    let (ymin, ymax) = pad_range(yrange);
+    let x = |v: f64| left + (v - xmin) / (xmax - xmin) * plot_width;
+    let y = |v: f64| bottom - (v - ymin) / (ymax - ymin) * plot_height;
+
+    writeln!(w, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#888"/>"##,
+        left, top + MARGIN_TOP, plot_width, plot_height)?;
+
+    for i in 0..=TICKS {
+        let xv = xmin + (xmax - xmin) * i as f64 / TICKS as f64;
+        let yv = ymin + (ymax - ymin) * i as f64 / TICKS as f64;
+        writeln!(w, r##"<line x1="{0}" y1="{1}" x2="{0}" y2="{2}" stroke="#ddd"/>"##, x(xv), top + MARGIN_TOP, bottom)?;
+        writeln!(w, r##"<text x="{}" y="{}" text-anchor="middle">{:.0}</text>"##, x(xv), bottom + 16.0, xv)?;
+        writeln!(w, r##"<line x1="{0}" y1="{1}" x2="{2}" y2="{1}" stroke="#ddd"/>"##, left, y(yv), left + plot_width)?;
+        writeln!(w, r##"<text x="{}" y="{}" text-anchor="end">{:.0}</text>"##, left - 6.0, y(yv) + 4.0, yv)?;
+    }
+    writeln!(w, r##"<text x="{}" y="{}" text-anchor="middle">{}</text>"##, left + plot_width / 2.0, bottom + 32.0, escape(x_label))?;
+    writeln!(w, r##"<text x="{}" y="{}" text-anchor="end">{}</text>"##, left - 6.0, top + MARGIN_TOP - 12.0, escape(panel.unit))?;
+
+    for (i, series) in panel.series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points = series.points.iter()
+            .map(|&(px, py)| format!("{:.1},{:.1}", x(px), y(py)))
+            .collect::<Vec<_>>().join(" ");
+        writeln!(w, r##"<polyline fill="none" stroke="{}" stroke-width="1.5" points="{}"/>"##, color, points)?;
+
+        let legend = top + MARGIN_TOP + 14.0 * i as f64;
+        writeln!(w, r##"<rect x="{}" y="{}" width="10" height="10" fill="{}"/>"##, left + plot_width + 10.0, legend, color)?;
+        writeln!(w, r##"<text x="{}" y="{}">{}</text>"##, left + plot_width + 24.0, legend + 9.0, escape(&series.name))?;
+    }
+
+    Ok(())
+}
+
+pub fn write_svg<W: Write>(mut w: W, x_label: &str, panels: &[Panel]) -> io::Result<()> {
+    let height = PANEL_HEIGHT * panels.len() as f64;
+
+    writeln!(w, r##"<?xml version="1.0" encoding="UTF-8"?>"##)?;
+    writeln!(w, r##"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="sans-serif" font-size="11">"##, WIDTH, height)?;
+    writeln!(w, r##"<rect width="100%" height="100%" fill="white"/>"##)?;
+    for (i, panel) in panels.iter().enumerate() {
+        write_panel(&mut w, panel, PANEL_HEIGHT * i as f64, x_label)?;
+    }
+    writeln!(w, "</svg>")
+}
+
+/// Base and offset frequency against voltage.
+pub fn vfp_svg<W: Write>(w: W, points: &[VfPoint]) -> io::Result<()> {
+    let voltage = |p: &VfPoint| p.voltage.0 as f64 / 1000.0;
+    let panel = Panel {
+        title: "Voltage-Frequency Curve".into(),
+        unit: "MHz",
+        series: vec![
+            Series {
+                name: "Base".into(),
+                points: points.iter().map(|p| (voltage(p), (p.frequency - p.delta).0 as f64 / 1000.0)).collect(),
+            },
+            Series {
+                name: "Offset".into(),
+                points: points.iter().map(|p| (voltage(p), p.frequency.0 as f64 / 1000.0)).collect(),
+            },
+        ],
+    };
+
+    write_svg(w, "Voltage (mV)", &[panel])
+}
+
+/// Clocks, temperatures, power and fan levels over time, `samples` holds the
+/// time in seconds of each sample along with the index and status of every GPU.
+pub fn log_svg<W: Write>(w: W, samples: &[(f64, Vec<(usize, GpuStatus)>)]) -> io::Result<()> {
+    let mut clocks = BTreeMap::new();
+    let mut sensors = BTreeMap::new();
+    let mut power = BTreeMap::new();
+    let mut coolers = BTreeMap::new();
+
+    let multiple = samples.iter().any(|&(_, ref gpus)| gpus.len() > 1);
+    let name = |gpu: usize, name: String| if multiple {
+        format!("GPU {} {}", gpu, name)
+    } else {
+        name
+    };
+
+    for &(time, ref gpus) in samples {
+        for &(gpu, ref status) in gpus {
+            for (clock, freq) in &status.clocks {
+                clocks.entry(name(gpu, clock.to_string())).or_insert_with(Vec::new)
+                    .push((time, freq.0 as f64 / 1000.0));
+            }
+            for (i, &(ref sensor, temp)) in status.sensors.iter().enumerate() {
+                sensors.entry(name(gpu, format!("{} {}", sensor.target, i))).or_insert_with(Vec::new)
+                    .push((time, temp.0 as f64));
+            }
+            for (i, power_usage) in status.power.iter().enumerate() {
+                power.entry(name(gpu, format!("Power {}", i))).or_insert_with(Vec::new)
+                    .push((time, power_usage.0 as f64));
+            }
+            for (i, &(ref cooler, ref level)) in status.coolers.iter().enumerate() {
+                coolers.entry(name(gpu, format!("{} {}", cooler.kind, i))).or_insert_with(Vec::new)
+                    .push((time, level.level.0 as f64));
+            }
+        }
+    }
+
+    write_svg(w, "Time (s)", &[
+        Panel::from_map("Clocks", "MHz", clocks),
+        Panel::from_map("Temperature", "C", sensors),
+        Panel::from_map("Power", "%", power),
+        Panel::from_map("Fan Level", "%", coolers),
+    ])
+}
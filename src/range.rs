@@ -0,0 +1,18 @@
+use std::fmt::Display;
+use nvapi::Range;
+use crate::Error;
+
+/// Rejects values the driver reports as outside of a setting's range.
+pub fn check<T: PartialOrd + Display + Copy>(setting: &'static str, value: T, range: &Range<T>) -> Result<T, Error>
+    where Range<T>: Display
+{
+    if value < range.min || value > range.max {
+        Err(Error::OutOfRange {
+            setting: setting,
+            value: value.to_string(),
+            range: range.to_string(),
+        })
+    } else {
+        Ok(value)
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::Display;
+use std::io::Write;
+use nvapi::GpuStatus;
+use crate::monitor::Tick;
+use crate::types::MetricGroup;
+use crate::Error;
+
+/// A single numeric reading taken from a `GpuStatus`.
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub group: MetricGroup,
+    pub name: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+    pub unit: Option<&'static str>,
+    pub value: f64,
+}
+
+impl Metric {
+    fn new(group: MetricGroup, name: &'static str, labels: Vec<(&'static str, String)>, unit: Option<&'static str>, value: f64) -> Self {
+        Metric {
+            group: group,
+            name: name,
+            labels: labels,
+            unit: unit,
+            value: value,
+        }
+    }
+
+    /// Name and unit without any labels, such as `clock_mhz`.
+    pub fn base_name(&self) -> String {
+        match self.unit {
+            Some(unit) => format!("{}_{}", self.name, unit),
+            None => self.name.to_string(),
+        }
+    }
+
+    /// Flat identifier such as `clock_graphics_mhz`.
+    pub fn column(&self) -> String {
+        let mut column = self.name.to_string();
+        for &(_, ref label) in &self.labels {
+            column.push('_');
+            column.push_str(label);
+        }
+        if let Some(unit) = self.unit {
+            column.push('_');
+            column.push_str(unit);
+        }
+        column
+    }
+}
+
+/// Lowercases a display name into something usable as an identifier.
+pub fn key<D: Display>(name: D) -> String {
+    name.to_string().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect()
+}
+
+pub fn metrics(status: &GpuStatus) -> Vec<Metric> {
+    let mut metrics = Vec::new();
+
+    for (clock, freq) in &status.clocks {
+        metrics.push(Metric::new(MetricGroup::Clocks, "clock", vec![("domain", key(clock))], Some("mhz"), freq.0 as f64 / 1000.0));
+    }
+
+    for (res, util) in &status.utilization {
+        metrics.push(Metric::new(MetricGroup::Utilization, "utilization", vec![("domain", key(res))], Some("percent"), util.0 as f64));
+    }
+
+    for (i, &(ref sensor, temp)) in status.sensors.iter().enumerate() {
+        metrics.push(Metric::new(MetricGroup::Sensors, "temperature", vec![("sensor", i.to_string()), ("target", key(&sensor.target))], Some("c"), temp.0 as f64));
+    }
+
+    for (i, power) in status.power.iter().enumerate() {
+        metrics.push(Metric::new(MetricGroup::Power, "power", vec![("index", i.to_string())], Some("percent"), power.0 as f64));
+    }
+
+    if let Some(voltage) = status.voltage {
+        metrics.push(Metric::new(MetricGroup::Voltage, "voltage", Vec::new(), Some("mv"), voltage.0 as f64 / 1000.0));
+    }
+
+    if let Some(tach) = status.tachometer {
+        metrics.push(Metric::new(MetricGroup::Tachometer, "tachometer", Vec::new(), Some("rpm"), tach as f64));
+    }
+
+    for (i, &(_, ref cooler)) in status.coolers.iter().enumerate() {
+        metrics.push(Metric::new(MetricGroup::Coolers, "cooler", vec![("cooler", i.to_string())], Some("percent"), cooler.level.0 as f64));
+    }
+
+    metrics.push(Metric::new(MetricGroup::PState, "pstate", Vec::new(), None, status.pstate as u32 as f64));
+
+    metrics
+}
+
+/// Names of the perf limits currently holding the GPU back.
+pub fn limits(status: &GpuStatus) -> Vec<String> {
+    status.perf.limits.map(|limit| limit.to_string()).collect()
+}
+
+/// Writes one flat CSV row per GPU per sample, with columns fixed by the first sample.
+pub struct CsvLog<W: Write> {
+    writer: csv::Writer<W>,
+    groups: Vec<MetricGroup>,
+    columns: Option<Vec<String>>,
+}
+
+impl<W: Write> CsvLog<W> {
+    pub fn new(write: W, groups: Vec<MetricGroup>) -> Self {
+        CsvLog {
+            writer: csv::Writer::from_writer(write),
+            groups: groups,
+            columns: None,
+        }
+    }
+
+    fn selected(&self, group: MetricGroup) -> bool {
+        self.groups.contains(&group)
+    }
+
+    pub fn write(&mut self, tick: &Tick, gpu: usize, status: &GpuStatus) -> Result<(), Error> {
+        let metrics = metrics(status).into_iter()
+            .filter(|m| self.selected(m.group))
+            .map(|m| (m.column(), m.value))
+            .collect::<Vec<_>>();
+        let limits = if self.selected(MetricGroup::Limits) {
+            Some(limits(status).join("|"))
+        } else {
+            None
+        };
+
+        if self.columns.is_none() {
+            let mut header = vec!["timestamp".to_string(), "elapsed".to_string(), "gpu".to_string()];
+            header.extend(metrics.iter().map(|&(ref column, _)| column.clone()));
+            if limits.is_some() {
+                header.push("limits".into());
+            }
+            self.writer.write_record(&header).map_err(std::io::Error::from)?;
+            self.columns = Some(header);
+        }
+
+        let row = self.columns.as_ref().unwrap().iter().map(|column| match &column[..] {
+            "timestamp" => tick.timestamp.to_rfc3339(),
+            "elapsed" => tick.elapsed.as_secs_f64().to_string(),
+            "gpu" => gpu.to_string(),
+            "limits" => limits.clone().unwrap_or_default(),
+            column => metrics.iter().find(|&&(ref c, _)| c == column)
+                .map(|&(_, value)| value.to_string())
+                .unwrap_or_default(),
+        }).collect::<Vec<_>>();
+        self.writer.write_record(&row).map_err(std::io::Error::from)?;
+
+        // monitor mode runs until interrupted, so don't sit on buffered rows
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+use std::str::FromStr;
+use nvapi::{Gpu, Status};
+use crate::Error;
+
+/// Where a GPU sits on the PCI bus, printed as `bus:device.function`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u32,
+    pub device: u32,
+    pub function: u32,
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{:x}", self.bus, self.device, self.function)
+    }
+}
+
+impl FromStr for PciAddress {
+    type Err = Error;
+
+    /// Accepts `01:00.0`, `01:00` and the `0000:01:00.0` form with a domain.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let invalid = || invalid(s, "PCI addresses look like `01:00.0`".into());
+        let parts = s.split(':').collect::<Vec<_>>();
+        let (bus, slot) = match *parts {
+            [bus, slot] | [_, bus, slot] => (bus, slot),
+            _ => return Err(invalid()),
+        };
+        let (device, function) = match slot.find('.') {
+            Some(i) => (&slot[..i], &slot[i + 1..]),
+            None => (slot, "0"),
+        };
+        let hex = |v: &str| u32::from_str_radix(v, 16).map_err(|_| invalid());
+
+        Ok(PciAddress {
+            bus: hex(bus)?,
+            device: hex(device)?,
+            function: hex(function)?,
+        })
+    }
+}
+
+/// What a GPU can be picked by, independent of the order it was enumerated in.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub index: usize,
+    pub name: String,
+    pub pci: PciAddress,
+    /// Device and subsystem ids along with the bus location, which stays the
+    /// same as long as the card isn't moved to another slot.
+    pub id: String,
+}
+
+impl Identity {
+    pub fn new(index: usize, gpu: &Gpu) -> Result<Self, Error> {
+        let info = gpu.info()?;
+        let pci = PciAddress {
+            bus: gpu.inner().bus_id()?,
+            device: gpu.inner().bus_slot_id()?,
+            function: 0,
+        };
+
+        Ok(Identity {
+            index: index,
+            name: gpu.inner().full_name()?,
+            id: format!("{:08x}-{:08x}-{:02x}{:02x}", info.pci.device_id, info.pci.subsystem_id, pci.bus, pci.device),
+            pci: pci,
+        })
+    }
+}
+
+/// A `-g` argument: `all`, an index such as `1`, a range such as `0-2`,
+/// `pci:01:00.0`, `name:*3080*` or `id:...` as printed by `list`.
+#[derive(Debug, Clone)]
+pub enum Selector {
+    All,
+    Indices { first: usize, last: usize },
+    Pci(PciAddress),
+    Name(String),
+    Id(String),
+}
+
+fn invalid(selector: &str, reason: String) -> Error {
+    Error::Selector {
+        selector: selector.into(),
+        reason: reason,
+    }
+}
+
+impl FromStr for Selector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let index = |v: &str| usize::from_str(v).map_err(|_| invalid(s, "expected an index, range, `all`, or a `pci:`, `name:` or `id:` selector".into()));
+
+        Ok(if s == "all" {
+            Selector::All
+        } else if let Some(address) = s.strip_prefix("pci:") {
+            Selector::Pci(address.parse().map_err(|_| invalid(s, "PCI addresses look like `01:00.0`".into()))?)
+        } else if let Some(name) = s.strip_prefix("name:") {
+            Selector::Name(name.to_lowercase())
+        } else if let Some(id) = s.strip_prefix("id:") {
+            Selector::Id(id.to_lowercase())
+        } else if let Some(i) = s.find('-') {
+            let (first, last) = (index(&s[..i])?, index(&s[i + 1..])?);
+            if first > last {
+                return Err(invalid(s, "range ends before it starts".into()))
+            }
+            Selector::Indices { first: first, last: last }
+        } else {
+            let i = index(s)?;
+            Selector::Indices { first: i, last: i }
+        })
+    }
+}
+
+/// Case-insensitive match where `*` stands for any run of characters and `?` for one.
+fn glob(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((&'*', rest)), _) => glob(rest, name) || (!name.is_empty() && glob(pattern, &name[1..])),
+        (Some((&'?', rest)), Some((_, name))) => glob(rest, name),
+        (Some((p, rest)), Some((n, name))) => p == n && glob(rest, name),
+        (Some(..), None) => false,
+    }
+}
+
+impl Selector {
+    fn matches(&self, identity: &Identity) -> bool {
+        match *self {
+            Selector::All => true,
+            Selector::Indices { first, last } => identity.index >= first && identity.index <= last,
+            Selector::Pci(address) => identity.pci == address,
+            Selector::Name(ref pattern) => glob(
+                &pattern.chars().collect::<Vec<_>>(),
+                &identity.name.to_lowercase().chars().collect::<Vec<_>>(),
+            ),
+            Selector::Id(ref id) => identity.id == *id,
+        }
+    }
+}
+
+/// GPUs picked by any of `selectors` along with their enumeration index,
+/// or all of them when none are given.
+pub fn select<'a, S: AsRef<str>, I: IntoIterator<Item=S>>(gpus: &'a [Gpu], selectors: Option<I>) -> Result<Vec<(usize, &'a Gpu)>, Error> {
+    let selectors = match selectors {
+        Some(selectors) => selectors.into_iter()
+            .map(|s| s.as_ref().parse().map(|selector| (s.as_ref().to_string(), selector)))
+            .collect::<Result<Vec<(String, Selector)>, _>>()?,
+        None => Vec::new(),
+    };
+
+    if gpus.is_empty() {
+        return Err(Status::NvidiaDeviceNotFound.into())
+    } else if selectors.is_empty() {
+        return Ok(gpus.iter().enumerate().collect())
+    }
+
+    let identities = gpus.iter().enumerate()
+        .map(|(i, gpu)| Identity::new(i, gpu))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut selected = vec![false; gpus.len()];
+    for (s, selector) in &selectors {
+        if let Selector::Indices { last, .. } = *selector {
+            if last >= gpus.len() {
+                return Err(invalid(s, format!("no GPU at index {} ({} detected)", last, gpus.len())))
+            }
+        }
+
+        let mut found = false;
+        for identity in &identities {
+            if selector.matches(identity) {
+                selected[identity.index] = true;
+                found = true;
+            }
+        }
+
+        if !found {
+            return Err(invalid(s, "matched no GPUs, see `list` for the available identifiers".into()))
+        }
+    }
+
+    Ok(gpus.iter().enumerate().filter(|&(i, _)| selected[i]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob, Identity, PciAddress, Selector};
+
+    fn pci(s: &str) -> PciAddress {
+        s.parse().unwrap()
+    }
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        glob(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn pci_address() {
+        let address = PciAddress { bus: 1, device: 0, function: 0 };
+        assert_eq!(pci("01:00.0"), address);
+        assert_eq!(pci("01:00"), address);
+        assert_eq!(pci("0000:01:00.0"), address);
+        assert_eq!(pci("0a:1f.3"), PciAddress { bus: 10, device: 31, function: 3 });
+        assert_eq!(pci("0a:1f.3").to_string(), "0a:1f.3");
+
+        for invalid in &["", "01", "01:00.0:1", "0000:01:00:00.0", "zz:00.0", "01:00.x"] {
+            assert!(invalid.parse::<PciAddress>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn selectors() {
+        match "all".parse().unwrap() {
+            Selector::All => (),
+            selector => panic!("{:?}", selector),
+        }
+        match "2".parse().unwrap() {
+            Selector::Indices { first: 2, last: 2 } => (),
+            selector => panic!("{:?}", selector),
+        }
+        match "0-2".parse().unwrap() {
+            Selector::Indices { first: 0, last: 2 } => (),
+            selector => panic!("{:?}", selector),
+        }
+        match "pci:0000:01:00.0".parse().unwrap() {
+            Selector::Pci(address) => assert_eq!(address, PciAddress { bus: 1, device: 0, function: 0 }),
+            selector => panic!("{:?}", selector),
+        }
+        match "name:*RTX 3080*".parse().unwrap() {
+            Selector::Name(ref pattern) => assert_eq!(pattern, "*rtx 3080*"),
+            selector => panic!("{:?}", selector),
+        }
+        match "id:2206-ABCD".parse().unwrap() {
+            Selector::Id(ref id) => assert_eq!(id, "2206-abcd"),
+            selector => panic!("{:?}", selector),
+        }
+
+        for invalid in &["2-0", "-1", "1-", "one", "pci:01", ""] {
+            assert!(invalid.parse::<Selector>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(matches("*3080*", "geforce rtx 3080 ti"));
+        assert!(matches("geforce*", "geforce gtx 1080"));
+        assert!(matches("gtx 10?0", "gtx 1080"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "abbbc"));
+        assert!(!matches("gtx 10?0", "gtx 100"));
+        assert!(!matches("*3080", "rtx 3080 ti"));
+        assert!(!matches("", "gtx"));
+    }
+
+    #[test]
+    fn selector_matches() {
+        let identity = Identity {
+            index: 1,
+            name: "NVIDIA GeForce GTX 1080".into(),
+            pci: PciAddress { bus: 1, device: 0, function: 0 },
+            id: "1b8010de-37321462-0100".into(),
+        };
+        let selects = |s: &str| s.parse::<Selector>().unwrap().matches(&identity);
+
+        assert!(selects("all"));
+        assert!(selects("1"));
+        assert!(selects("0-2"));
+        assert!(!selects("2-3"));
+        assert!(selects("pci:01:00.0"));
+        assert!(!selects("pci:02:00.0"));
+        assert!(selects("name:*gtx 1080"));
+        assert!(!selects("name:*1070*"));
+        assert!(selects("id:1B8010DE-37321462-0100"));
+    }
+}
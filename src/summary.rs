@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde::Serialize;
+use schemars::JsonSchema;
+use nvapi::GpuStatus;
+use crate::monitor::Tick;
+use crate::sample::{self, Metric};
+use crate::types::MetricGroup;
+
+/// Statistics for a single metric over a monitor session.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MetricSummary {
+    pub metric: String,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p95: f64,
+}
+
+/// How often a perf limit was active, as a share of samples.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LimitSummary {
+    pub limit: String,
+    pub samples: u64,
+    pub share: f64,
+}
+
+/// Time spent in a PState, attributed from each sample until the next.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PStateSummary {
+    pub pstate: String,
+    pub seconds: f64,
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct GpuSummary {
+    pub gpu: usize,
+    pub samples: u64,
+    pub metrics: Vec<MetricSummary>,
+    pub limits: Vec<LimitSummary>,
+    pub pstates: Vec<PStateSummary>,
+}
+
+#[derive(Debug, Default)]
+struct GpuHistory {
+    samples: u64,
+    metrics: Vec<(String, Vec<f64>)>,
+    limits: BTreeMap<String, u64>,
+    pstates: BTreeMap<String, Duration>,
+    last: Option<(Duration, String)>,
+}
+
+/// Accumulates monitor samples for a summary once monitoring ends.
+pub struct Summary {
+    groups: Vec<MetricGroup>,
+    gpus: BTreeMap<usize, GpuHistory>,
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+impl Summary {
+    pub fn new(groups: Vec<MetricGroup>) -> Self {
+        Summary {
+            groups: groups,
+            gpus: Default::default(),
+        }
+    }
+
+    fn selected(&self, metric: &Metric) -> bool {
+        // pstates are summarized as time spent in each instead
+        metric.group != MetricGroup::PState && self.groups.contains(&metric.group)
+    }
+
+    pub fn add(&mut self, tick: &Tick, gpu: usize, status: &GpuStatus) {
+        let metrics = sample::metrics(status).into_iter()
+            .filter(|m| self.selected(m))
+            .collect::<Vec<_>>();
+        let limits = if self.groups.contains(&MetricGroup::Limits) {
+            sample::limits(status)
+        } else {
+            Vec::new()
+        };
+        let history = self.gpus.entry(gpu).or_insert_with(Default::default);
+        history.samples += 1;
+
+        for metric in metrics {
+            let column = metric.column();
+            match history.metrics.iter_mut().find(|entry| entry.0 == column) {
+                Some(entry) => entry.1.push(metric.value),
+                None => history.metrics.push((column, vec![metric.value])),
+            }
+        }
+
+        for limit in limits {
+            *history.limits.entry(limit).or_insert(0) += 1;
+        }
+
+        if let Some((elapsed, pstate)) = history.last.take() {
+            *history.pstates.entry(pstate).or_insert_with(Duration::default) += tick.elapsed - elapsed;
+        }
+        history.last = Some((tick.elapsed, status.pstate.to_string()));
+    }
+
+    pub fn summarize(&self) -> Vec<GpuSummary> {
+        self.gpus.iter().map(|(&gpu, history)| {
+            let total = history.pstates.values().cloned().sum::<Duration>().as_secs_f64();
+
+            GpuSummary {
+                gpu: gpu,
+                samples: history.samples,
+                metrics: history.metrics.iter().map(|&(ref metric, ref values)| {
+                    let mut sorted = values.clone();
+                    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+                    MetricSummary {
+                        metric: metric.clone(),
+                        min: sorted[0],
+                        max: sorted[sorted.len() - 1],
+                        mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+                        p95: percentile(&sorted, 95.0),
+                    }
+                }).collect(),
+                limits: history.limits.iter().map(|(limit, &samples)| LimitSummary {
+                    limit: limit.clone(),
+                    samples: samples,
+                    share: samples as f64 / history.samples as f64,
+                }).collect(),
+                pstates: history.pstates.iter().map(|(pstate, time)| PStateSummary {
+                    pstate: pstate.clone(),
+                    seconds: time.as_secs_f64(),
+                    share: if total > 0.0 { time.as_secs_f64() / total } else { 0.0 },
+                }).collect(),
+            }
+        }).collect()
+    }
+}
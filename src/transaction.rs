@@ -0,0 +1,193 @@
+use std::fmt::Display;
+use nvapi::{Gpu, GpuSettings, ClockDomain, ClockLockMode, CoolerLevel, Percentage};
+use crate::gpu::set_vfp_deltas;
+use crate::types::ResetSettings;
+use crate::human;
+use crate::Error;
+
+/// What a pass over the requested changes does with them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Checks every value without writing anything.
+    Validate,
+    /// Prints the planned changes without writing anything.
+    DryRun,
+    Apply,
+}
+
+/// A setting going from its current value to a new one.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub gpu: usize,
+    pub setting: String,
+    pub current: String,
+    pub new: String,
+}
+
+impl Change {
+    pub fn new<S: Into<String>, C: Display, N: Display>(gpu: usize, setting: S, current: C, new: N) -> Self {
+        Change {
+            gpu: gpu,
+            setting: setting.into(),
+            current: current.to_string(),
+            new: new.to_string(),
+        }
+    }
+}
+
+/// Comma separated values, such as one power limit per policy.
+pub fn list<T: Display, I: IntoIterator<Item=T>>(values: I) -> String {
+    let values = values.into_iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    if values.is_empty() {
+        "N/A".into()
+    } else {
+        values.join(", ")
+    }
+}
+
+/// A GPU's settings from before `set` first changed it, and what was changed since.
+struct Snapshot {
+    index: usize,
+    settings: GpuSettings,
+    touched: Vec<ResetSettings>,
+    /// The curves written under `ResetSettings::VfpDeltas`.
+    vfp_domains: Vec<ClockDomain>,
+}
+
+/// The outcome of restoring one setting.
+pub struct Rollback {
+    pub gpu: usize,
+    pub setting: ResetSettings,
+    pub result: Result<(), Error>,
+}
+
+/// Tracks the changes made by `set` so they can be undone as a unit.
+pub struct Transaction {
+    mode: Mode,
+    snapshots: Vec<Snapshot>,
+}
+
+impl Transaction {
+    pub fn new(mode: Mode) -> Self {
+        Transaction {
+            mode: mode,
+            snapshots: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    fn snapshot_index(&mut self, index: usize, gpu: &Gpu) -> Result<usize, Error> {
+        Ok(match self.snapshots.iter().position(|s| s.index == index) {
+            Some(i) => i,
+            None => {
+                self.snapshots.push(Snapshot {
+                    index: index,
+                    settings: gpu.settings()?,
+                    touched: Vec::new(),
+                    vfp_domains: Vec::new(),
+                });
+                self.snapshots.len() - 1
+            },
+        })
+    }
+
+    /// The GPU's settings from before any change was made to it.
+    pub fn settings(&mut self, index: usize, gpu: &Gpu) -> Result<&GpuSettings, Error> {
+        let i = self.snapshot_index(index, gpu)?;
+        Ok(&self.snapshots[i].settings)
+    }
+
+    /// Call before writing `setting`, returns whether it should actually be written.
+    pub fn change(&mut self, index: usize, gpu: &Gpu, setting: ResetSettings, changes: &[Change]) -> Result<bool, Error> {
+        match self.mode {
+            Mode::Validate => Ok(false),
+            Mode::DryRun => {
+                for change in changes {
+                    human::print_change(change);
+                }
+                Ok(false)
+            },
+            Mode::Apply => {
+                let i = self.snapshot_index(index, gpu)?;
+                let touched = &mut self.snapshots[i].touched;
+                if !touched.contains(&setting) {
+                    touched.push(setting);
+                }
+                Ok(true)
+            },
+        }
+    }
+
+    /// `change` for the VFP deltas of a single domain's curve.
+    pub fn change_vfp(&mut self, index: usize, gpu: &Gpu, domain: ClockDomain, changes: &[Change]) -> Result<bool, Error> {
+        let write = self.change(index, gpu, ResetSettings::VfpDeltas, changes)?;
+        if write {
+            let i = self.snapshot_index(index, gpu)?;
+            let domains = &mut self.snapshots[i].vfp_domains;
+            if !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+
+        Ok(write)
+    }
+
+    /// Restores every touched setting, most recent first. A failed restore
+    /// doesn't stop the rest from being attempted.
+    pub fn rollback(self, gpus: &[(usize, &Gpu)]) -> Vec<Rollback> {
+        self.snapshots.iter().rev().flat_map(|snapshot| {
+            let gpu = gpus.iter().find(|&&(i, _)| i == snapshot.index).map(|&(_, gpu)| gpu);
+            snapshot.touched.iter().rev().map(move |&setting| Rollback {
+                gpu: snapshot.index,
+                setting: setting,
+                result: match gpu {
+                    Some(gpu) => restore(gpu, setting, snapshot),
+                    None => Err("GPU is no longer selected".into()),
+                },
+            })
+        }).collect()
+    }
+}
+
+fn restore(gpu: &Gpu, setting: ResetSettings, snapshot: &Snapshot) -> Result<(), Error> {
+    let set = &snapshot.settings;
+    match setting {
+        ResetSettings::VoltageBoost =>
+            gpu.set_voltage_boost(set.voltage_boost.unwrap_or(Percentage(0)))?,
+        ResetSettings::SensorLimits =>
+            gpu.set_sensor_limits(set.sensor_limits.iter().cloned())?,
+        ResetSettings::PowerLimits =>
+            gpu.set_power_limits(set.power_limits.iter().cloned())?,
+        ResetSettings::CoolerLevels =>
+            gpu.set_cooler_levels(set.coolers.iter().map(|&(_, ref cooler)| CoolerLevel {
+                policy: cooler.policy,
+                level: cooler.level,
+            }))?,
+        ResetSettings::VfpDeltas => if let Some(ref vfp) = set.vfp {
+            for &domain in &snapshot.vfp_domains {
+                let deltas = match domain {
+                    ClockDomain::Memory => &vfp.memory,
+                    _ => &vfp.graphics,
+                };
+                set_vfp_deltas(gpu, domain, deltas.iter().map(|(&i, &delta)| (i, delta)))?;
+            }
+        },
+        ResetSettings::VfpLock => match set.vfp_locks.values()
+            .filter(|lock| lock.mode == ClockLockMode::Manual).map(|lock| lock.voltage).max()
+        {
+            Some(voltage) => gpu.set_vfp_lock(voltage)?,
+            None => gpu.reset_vfp_lock()?,
+        },
+        ResetSettings::PStateDeltas =>
+            gpu.inner().set_pstates(set.pstate_deltas.iter()
+                .flat_map(|(&pstate, deltas)| deltas.iter().map(move |(&clock, &delta)| (pstate, clock, delta)))
+            )?,
+        ResetSettings::Overvolt =>
+            gpu.inner().set_overvolt(set.overvolt.iter().cloned())?,
+    }
+
+    Ok(())
+}
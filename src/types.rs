@@ -1,18 +1,44 @@
 use clap::ArgMatches;
 use serde::Serialize;
+use nvapi::{Kibibytes, Microvolts};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct GpuDescriptor {
+    pub index: usize,
     pub name: String,
+    /// PCI bus location, selected with `-g pci:ADDRESS`.
+    pub address: String,
+    /// Selected with `-g id:ID`.
+    pub id: String,
+    /// Device, subsystem and revision ids.
+    pub pci: String,
+    pub bios_version: String,
+    pub memory: Kibibytes,
+    pub vfp: bool,
+    /// `None` when the GPU's status or settings couldn't be read.
+    pub state: Option<GpuState>,
+}
+
+/// What has been changed on a GPU.
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuState {
+    /// Voltage of a manual VFP lock.
+    pub vfp_lock: Option<Microvolts>,
+    /// Settings that differ from their defaults, named like `reset` names them.
+    pub modified: Vec<String>,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum OutputFormat {
     Human,
     Json,
+    Ndjson,
+    Yaml,
+    Toml,
+    Influx,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ResetSettings {
     VoltageBoost,
     SensorLimits,
@@ -24,6 +50,35 @@ pub enum ResetSettings {
     Overvolt,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MetricGroup {
+    Clocks,
+    Utilization,
+    Sensors,
+    Power,
+    Voltage,
+    Tachometer,
+    Coolers,
+    PState,
+    Limits,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum CurveFormat {
+    Csv,
+    Json,
+    Toml,
+    Afterburner,
+    Svg,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CurveFix {
+    Clamp,
+    Smooth,
+    Quantize,
+}
+
 pub const POSSIBLE_BOOL_OFF: &'static str = "off";
 pub const POSSIBLE_BOOL_ON: &'static str = "on";
 pub const POSSIBLE_BOOL: &'static [&'static str] = &[POSSIBLE_BOOL_OFF, POSSIBLE_BOOL_ON];